@@ -0,0 +1,439 @@
+use bevy::{
+    app::App,
+    ecs::{
+        change_detection::DetectChangesMut,
+        component::Component,
+        entity::Entity,
+        event::{Event, EventWriter},
+        schedule::{IntoSystemConfigs, SystemSet},
+        system::{Commands, Query, Res, ResMut},
+    },
+    hierarchy::DespawnRecursiveExt,
+    math::{U16Vec2, Vec2},
+};
+use ratatui::{
+    buffer::Cell,
+    style::Color,
+};
+use std::{
+    sync::Arc,
+    time::{Duration, Instant},
+};
+use crate::{
+    float::{Anchor, Floating},
+    focus::not_paused,
+    render::{DrawBuffer, DrawFrame, RenderWidgets},
+    time::{Clock, RenderTimeout},
+};
+
+pub(crate) fn build(app: &mut App, _: &crate::Foxin) {
+    app.add_event::<AnimationFinished>();
+    // Animations hold still while paused, and pick up where they left off
+    app.add_systems(crate::schedule::PreLogic, (follow_paths, tween_sizes)
+        .chain()
+        .run_if(not_paused)
+    );
+    // Drawing a frame uses up the request for it, so ask again once drawn
+    app.add_systems(crate::schedule::PostLogic, request_animation_frames.run_if(not_paused));
+    app.add_systems(crate::schedule::PostRender, (finish_animations, request_animation_frames)
+        .chain()
+        .after(DrawFrame)
+        .run_if(not_paused)
+    );
+    app.add_systems(crate::schedule::Render, (render_cell_frames, render_color_tweens)
+        .chain()
+        .in_set(RenderAnimations)
+        .after(RenderWidgets)
+        .run_if(not_paused)
+    );
+}
+
+pub(crate) fn cleanup(_: &mut App) {}
+
+pub trait AnimateAppExt {
+    /// Let [`Track<C>`] change `C` as animations play
+    fn animate_component<C: Component>(&mut self) -> &mut Self;
+}
+
+impl AnimateAppExt for App {
+    fn animate_component<C: Component>(&mut self) -> &mut Self {
+        self.add_systems(crate::schedule::PreLogic, apply_tracks::<C>.run_if(not_paused))
+    }
+}
+
+/// Draws [`CellFrames`] and [`ColorTween`]s, after widgets so tweens can
+/// recolor them
+#[derive(SystemSet, Hash, Copy, Clone, PartialEq, Eq, Debug)]
+pub struct RenderAnimations;
+
+/// Something that changes over time. Foxin renders whenever its next frame
+/// is due, and stops once every animation has finished, so an idle game
+/// takes no CPU. Render systems draw it with [`Animation::frame`] or
+/// [`Animation::progress`].
+#[derive(Component, Debug, Clone)]
+pub struct Animation {
+    pub started: Instant,
+    /// How long each visible step lasts
+    pub frame_time: Duration,
+    /// `None` repeats forever
+    pub length: Option<Duration>,
+    pub on_finish: OnFinish,
+}
+
+/// What happens to an entity when its [`Animation`] finishes
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq)]
+pub enum OnFinish {
+    /// Remove the animation, leaving the entity as the last frame drew it
+    #[default]
+    Remove,
+    /// Despawn the entity and its descendants, e.g. for a projectile
+    Despawn,
+}
+
+/// Sent when an [`Animation`] finishes, before [`OnFinish`] applies
+#[derive(Event, Debug, Copy, Clone)]
+pub struct AnimationFinished(pub Entity);
+
+impl Animation {
+    /// Starts at `now`, from the [`Clock`]
+    pub fn new(now: Instant, frame_time: Duration, length: Option<Duration>) -> Self {
+        Self {
+            started: now,
+            frame_time,
+            length,
+            on_finish: OnFinish::Remove,
+        }
+    }
+
+    /// Repeats until removed, e.g. a blinking cursor
+    pub fn repeating(now: Instant, frame_time: Duration) -> Self {
+        Self::new(now, frame_time, None)
+    }
+
+    /// Plays once over `length`, e.g. a hit flash
+    pub fn once(now: Instant, frame_time: Duration, length: Duration) -> Self {
+        Self::new(now, frame_time, Some(length))
+    }
+
+    pub fn despawn_on_finish(mut self) -> Self {
+        self.on_finish = OnFinish::Despawn;
+        self
+    }
+
+    pub fn elapsed(&self, now: Instant) -> Duration {
+        let elapsed = now.saturating_duration_since(self.started);
+        match self.length {
+            Some(length) => elapsed.min(length),
+            None => elapsed,
+        }
+    }
+
+    /// Which step the animation is on. Animations that play once stay on
+    /// their last step when finished.
+    pub fn frame(&self, now: Instant) -> u32 {
+        if self.frame_time.is_zero() {
+            return 0;
+        }
+        let frame = self.elapsed(now).as_nanos() / self.frame_time.as_nanos();
+        let last = self.length
+            .map(|length| length.as_nanos().saturating_sub(1) / self.frame_time.as_nanos())
+            .unwrap_or(u128::MAX);
+        frame.min(last) as u32
+    }
+
+    /// From 0.0 at the start to 1.0 at the end, or 0.0 when repeating
+    pub fn progress(&self, now: Instant) -> f32 {
+        match self.length {
+            Some(length) if !length.is_zero() => {
+                self.elapsed(now).as_secs_f32() / length.as_secs_f32()
+            },
+            Some(_) => 1.0,
+            None => 0.0,
+        }
+    }
+
+    pub fn is_finished(&self, now: Instant) -> bool {
+        self.length.is_some_and(|length| now.saturating_duration_since(self.started) >= length)
+    }
+
+    /// When the next step starts, or the animation ends if that's sooner.
+    /// Once finished, now, so the end state gets drawn.
+    fn next_frame_at(&self, now: Instant) -> Instant {
+        if self.is_finished(now) {
+            return now;
+        }
+        let next = self.started + self.frame_time * (self.frame(now) + 1);
+        match self.length {
+            Some(length) => next.min(self.started + length),
+            None => next,
+        }
+    }
+}
+
+/// Fills the entity's [`DrawBuffer`] with each cell in turn as its
+/// [`Animation`] plays, e.g. blinking between a glyph and an empty cell
+#[derive(Component, Debug, Clone)]
+pub struct CellFrames(pub Vec<Cell>);
+
+/// Blends the colors of the entity's [`DrawBuffer`] from `from` to `to` as
+/// its [`Animation`] plays, e.g. fading a hit flash
+#[derive(Component, Debug, Copy, Clone)]
+pub struct ColorTween {
+    pub from: Color,
+    pub to: Color,
+    pub target: TweenTarget,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum TweenTarget {
+    Foreground,
+    Background,
+}
+
+/// Moves the top left corner of the entity's [`Floating`] area along these
+/// terminal positions as its [`Animation`] plays, at an even speed
+#[derive(Component, Debug, Clone)]
+pub struct PathTween(pub Vec<U16Vec2>);
+
+/// Resizes the entity's [`Floating`] area as its [`Animation`] plays, e.g.
+/// to open a window
+#[derive(Component, Debug, Copy, Clone)]
+pub struct SizeTween {
+    pub from: U16Vec2,
+    pub to: U16Vec2,
+}
+
+/// Calls back with the [`Animation::progress`] every frame the entity's
+/// animation is drawn, to change its `C` however it likes, e.g. to move a
+/// projectile. The app needs
+/// [`animate_component::<C>`](AnimateAppExt::animate_component).
+#[derive(Component, Clone)]
+pub struct Track<C: Component>(Arc<TrackFn<C>>);
+
+type TrackFn<C> = dyn Fn(&mut C, f32) + Send + Sync;
+
+impl<C: Component> Track<C> {
+    pub fn new(f: impl Fn(&mut C, f32) + Send + Sync + 'static) -> Self {
+        Self(Arc::new(f))
+    }
+}
+
+/// The point `progress` of the way along `path`, by distance travelled
+pub fn along_path(path: &[Vec2], progress: f32) -> Vec2 {
+    let lengths = path.windows(2).map(|pair| pair[0].distance(pair[1]));
+    let mut left = lengths.clone().sum::<f32>() * progress.clamp(0.0, 1.0);
+    for (pair, length) in path.windows(2).zip(lengths) {
+        if left <= length {
+            return pair[0].lerp(pair[1], if length > 0.0 { left / length } else { 1.0 });
+        }
+        left -= length;
+    }
+    path.last().copied().unwrap_or_default()
+}
+
+fn apply_tracks<C: Component>(
+    mut tracks: Query<(&Track<C>, &Animation, &mut C)>,
+    clock: Res<Clock>,
+) {
+    let now = clock.now();
+    for (track, animation, mut component) in tracks.iter_mut() {
+        (track.0)(&mut component, animation.progress(now));
+    }
+}
+
+fn follow_paths(
+    mut paths: Query<(&PathTween, &Animation, &mut Floating)>,
+    clock: Res<Clock>,
+) {
+    let now = clock.now();
+    for (path, animation, floating) in paths.iter_mut() {
+        let points = path.0.iter().map(|point| point.as_vec2()).collect::<Vec<_>>();
+        let at = along_path(&points, animation.progress(now)).round().as_u16vec2();
+        floating.map_unchanged(|floating| &mut floating.anchor).set_if_neq(Anchor::At(at));
+    }
+}
+
+fn tween_sizes(
+    mut sizes: Query<(&SizeTween, &Animation, &mut Floating)>,
+    clock: Res<Clock>,
+) {
+    let now = clock.now();
+    for (tween, animation, floating) in sizes.iter_mut() {
+        let size = tween.from.as_vec2().lerp(tween.to.as_vec2(), animation.progress(now));
+        floating.map_unchanged(|floating| &mut floating.size).set_if_neq(size.round().as_u16vec2());
+    }
+}
+
+fn request_animation_frames(
+    animations: Query<&Animation>,
+    mut timeout: ResMut<RenderTimeout>,
+    clock: Res<Clock>,
+) {
+    let now = clock.now();
+    for animation in animations.iter() {
+        timeout.by(animation.next_frame_at(now));
+    }
+}
+
+/// Runs once the end state has been drawn
+fn finish_animations(
+    mut commands: Commands,
+    animations: Query<(Entity, &Animation)>,
+    mut finished: EventWriter<AnimationFinished>,
+    mut timeout: ResMut<RenderTimeout>,
+    clock: Res<Clock>,
+) {
+    let now = clock.now();
+    for (entity, animation) in animations.iter() {
+        if !animation.is_finished(now) {
+            continue;
+        }
+        finished.send(AnimationFinished(entity));
+        match animation.on_finish {
+            OnFinish::Remove => { commands.entity(entity).remove::<Animation>(); },
+            OnFinish::Despawn => {
+                commands.entity(entity).despawn_recursive();
+                // Changes made while rendering don't ask for a frame, and
+                // whatever it drew needs drawing over
+                timeout.by(now);
+            },
+        }
+    }
+}
+
+fn render_cell_frames(
+    mut cells: Query<(&CellFrames, &Animation, &mut DrawBuffer)>,
+    clock: Res<Clock>,
+) {
+    let now = clock.now();
+    for (frames, animation, mut buffer) in cells.iter_mut() {
+        if frames.0.is_empty() {
+            continue;
+        }
+        let cell = &frames.0[animation.frame(now) as usize % frames.0.len()];
+        for c in buffer.0.content.iter_mut() {
+            c.clone_from(cell);
+        }
+    }
+}
+
+fn render_color_tweens(
+    mut tweens: Query<(&ColorTween, &Animation, &mut DrawBuffer)>,
+    clock: Res<Clock>,
+) {
+    let now = clock.now();
+    for (tween, animation, mut buffer) in tweens.iter_mut() {
+        let color = crate::blend::mix(tween.from, tween.to, animation.progress(now))
+            .unwrap_or(if animation.progress(now) < 0.5 { tween.from } else { tween.to });
+        for cell in buffer.0.content.iter_mut() {
+            match tween.target {
+                TweenTarget::Foreground => cell.fg = color,
+                TweenTarget::Background => cell.bg = color,
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bevy::ecs::{
+        event::Events,
+        schedule::Schedule,
+        world::World,
+    };
+
+    fn ms(ms: u64) -> Duration {
+        Duration::from_millis(ms)
+    }
+
+    #[test]
+    fn elapsed_stops_at_the_end() {
+        let mut clock = Clock::manual();
+        let once = Animation::once(clock.now(), ms(10), ms(35));
+        let repeating = Animation::repeating(clock.now(), ms(10));
+        clock.advance(ms(20));
+        assert_eq!(once.elapsed(clock.now()), ms(20));
+        clock.advance(ms(80));
+        assert_eq!(once.elapsed(clock.now()), ms(35));
+        assert_eq!(repeating.elapsed(clock.now()), ms(100));
+        assert_eq!(once.progress(clock.now()), 1.0);
+    }
+
+    #[test]
+    fn frames_step_with_the_frame_time() {
+        let mut clock = Clock::manual();
+        let start = clock.now();
+        let once = Animation::once(start, ms(10), ms(35));
+        assert_eq!(once.frame(clock.now()), 0);
+        assert_eq!(once.next_frame_at(clock.now()), start + ms(10));
+        clock.advance(ms(19));
+        assert_eq!(once.frame(clock.now()), 1);
+        clock.advance(ms(11));
+        assert_eq!(once.frame(clock.now()), 3);
+        // The last step is cut short by the end
+        assert_eq!(once.next_frame_at(clock.now()), start + ms(35));
+        clock.advance(ms(100));
+        assert_eq!(once.frame(clock.now()), 3);
+        assert!(once.is_finished(clock.now()));
+    }
+
+    #[test]
+    fn finished_animations_are_removed_or_despawned() {
+        let mut world = World::new();
+        world.insert_resource(Clock::manual());
+        world.init_resource::<RenderTimeout>();
+        world.init_resource::<Events<AnimationFinished>>();
+        let now = world.resource::<Clock>().now();
+        let kept = world.spawn(Animation::once(now, ms(10), ms(30))).id();
+        let despawned = world.spawn(Animation::once(now, ms(10), ms(20)).despawn_on_finish()).id();
+        let mut schedule = Schedule::default();
+        schedule.add_systems(finish_animations);
+
+        world.resource_mut::<Clock>().advance(ms(20));
+        schedule.run(&mut world);
+        assert!(world.get_entity(despawned).is_none());
+        assert!(world.get::<Animation>(kept).is_some());
+
+        world.resource_mut::<Clock>().advance(ms(10));
+        schedule.run(&mut world);
+        assert!(world.get::<Animation>(kept).is_none());
+        let finished = world.resource_mut::<Events<AnimationFinished>>()
+            .drain()
+            .map(|finished| finished.0)
+            .collect::<Vec<_>>();
+        assert_eq!(finished, [despawned, kept]);
+    }
+
+    #[test]
+    fn paths_are_followed_at_an_even_speed() {
+        let path = [Vec2::ZERO, Vec2::new(3.0, 0.0), Vec2::new(3.0, 1.0)];
+        assert_eq!(along_path(&path, 0.0), Vec2::ZERO);
+        assert_eq!(along_path(&path, 0.5), Vec2::new(2.0, 0.0));
+        assert_eq!(along_path(&path, 1.0), Vec2::new(3.0, 1.0));
+        assert_eq!(along_path(&path, 2.0), Vec2::new(3.0, 1.0));
+        assert_eq!(along_path(&path[..1], 0.5), Vec2::ZERO);
+    }
+
+    #[test]
+    fn tracks_move_floating_areas() {
+        let mut world = World::new();
+        world.insert_resource(Clock::manual());
+        let now = world.resource::<Clock>().now();
+        let entity = world.spawn((
+            Animation::once(now, ms(10), ms(40)),
+            Floating::new(Anchor::TopLeft, U16Vec2::ONE),
+            PathTween(vec![U16Vec2::new(0, 0), U16Vec2::new(8, 0)]),
+            SizeTween { from: U16Vec2::new(2, 2), to: U16Vec2::new(6, 4) },
+        )).id();
+        let mut schedule = Schedule::default();
+        schedule.add_systems((follow_paths, tween_sizes).chain());
+
+        world.resource_mut::<Clock>().advance(ms(10));
+        schedule.run(&mut world);
+        assert_eq!(
+            *world.get::<Floating>(entity).unwrap(),
+            Floating::new(Anchor::At(U16Vec2::new(2, 0)), U16Vec2::new(3, 3)),
+        );
+    }
+}
@@ -0,0 +1,267 @@
+use bevy::{
+    app::App,
+    ecs::{component::Component, system::Resource},
+};
+use ratatui::{
+    buffer::{Buffer, Cell},
+    style::{Color, Modifier},
+};
+use crate::render::RenderAppExt;
+
+pub(crate) fn build(app: &mut App, foxin: &crate::Foxin) {
+    app.insert_resource(foxin.default_colors);
+    app.redraw_on_change::<Blend>()
+        .redraw_on_change::<Dim>()
+        .redraw_on_change::<Opacity>();
+}
+
+pub(crate) fn cleanup(_: &mut App) {}
+
+/// The symbol of a cell with no content. Layers below show through it,
+/// unlike a space, which is drawn.
+pub const TRANSPARENT: &str = "";
+
+pub fn transparent_cell() -> Cell {
+    let mut cell = Cell::default();
+    cell.set_symbol(TRANSPARENT);
+    cell
+}
+
+pub fn is_transparent(cell: &Cell) -> bool {
+    cell.symbol() == TRANSPARENT
+}
+
+/// How an entity's [`DrawBuffer`](crate::render::DrawBuffer) is drawn over
+/// whatever is beneath it
+#[derive(Component, Debug, Default, Copy, Clone, PartialEq, Eq)]
+pub enum Blend {
+    /// Cells with content replace what's beneath. Cells without content
+    /// but with a background are filled with that background.
+    #[default]
+    Normal,
+    /// Only symbols and their foreground colors are drawn
+    Foreground,
+    /// Only background colors are drawn, tinting what's beneath
+    Background,
+}
+
+/// Darkens everything beneath the layer it is on, by 0.0 (not at all) to
+/// 1.0 (black)
+#[derive(Component, Debug, Copy, Clone, PartialEq)]
+pub struct Dim(pub f32);
+
+/// How much of a layer covers what's beneath it, from 0.0 to 1.0. Colors
+/// are mixed where both are known, otherwise whichever is over half wins.
+#[derive(Component, Debug, Copy, Clone, PartialEq)]
+pub struct Opacity(pub f32);
+
+/// What [`Color::Reset`] is taken to be where a real color is needed, e.g.
+/// to [`Dim`] it. Set these to the terminal theme's default colors.
+#[derive(Resource, Debug, Copy, Clone, PartialEq, Eq)]
+pub struct DefaultColors {
+    pub fg: Color,
+    pub bg: Color,
+}
+
+impl Default for DefaultColors {
+    fn default() -> Self {
+        Self {
+            fg: Color::Gray,
+            bg: Color::Black,
+        }
+    }
+}
+
+/// Turn a cell into the form layer caches hold, where an empty symbol is a
+/// see-through symbol and a reset background a see-through background
+pub(crate) fn canonical(cell: &Cell, blend: Blend) -> Cell {
+    let mut cell = cell.clone();
+    match blend {
+        Blend::Normal => {
+            if is_transparent(&cell) && cell.bg != Color::Reset {
+                cell.set_symbol(" ");
+            }
+        },
+        Blend::Foreground => {
+            cell.bg = Color::Reset;
+        },
+        Blend::Background => {
+            cell.set_symbol(TRANSPARENT);
+        },
+    }
+    cell
+}
+
+/// Draw a canonical cell over another
+pub(crate) fn over(upper: &Cell, lower: &mut Cell, opacity: f32) {
+    if !is_transparent(upper) {
+        let fg = match opacity >= 1.0 {
+            true => Some(upper.fg),
+            false => mix(lower.bg, upper.fg, opacity)
+                .or(Some(upper.fg).filter(|_| opacity >= 0.5)),
+        };
+        if let Some(fg) = fg {
+            lower.set_symbol(upper.symbol());
+            lower.fg = fg;
+            lower.modifier = upper.modifier;
+        }
+    }
+
+    if upper.bg != Color::Reset {
+        lower.bg = match opacity >= 1.0 {
+            true => upper.bg,
+            false => mix(lower.bg, upper.bg, opacity)
+                .unwrap_or(if opacity >= 0.5 { upper.bg } else { lower.bg }),
+        };
+    }
+}
+
+pub(crate) fn dim_buffer(buffer: &mut Buffer, amount: f32, defaults: &DefaultColors) {
+    let known = |color, default| if color == Color::Reset { default } else { color };
+    for cell in buffer.content.iter_mut() {
+        cell.fg = match darken(known(cell.fg, defaults.fg), amount) {
+            Some(color) => color,
+            None => {
+                cell.modifier.insert(Modifier::DIM);
+                cell.fg
+            },
+        };
+        cell.bg = darken(known(cell.bg, defaults.bg), amount).unwrap_or(cell.bg);
+    }
+}
+
+fn darken(color: Color, amount: f32) -> Option<Color> {
+    let (r, g, b) = rgb(color)?;
+    let scale = |c: u8| (c as f32 * (1.0 - amount.clamp(0.0, 1.0))) as u8;
+    Some(Color::Rgb(scale(r), scale(g), scale(b)))
+}
+
+/// `from` blended towards `to` by `t`, if both colors are known
+pub(crate) fn mix(from: Color, to: Color, t: f32) -> Option<Color> {
+    let (a, b) = (rgb(from)?, rgb(to)?);
+    let t = t.clamp(0.0, 1.0);
+    let lerp = |a: u8, b: u8| (a as f32 + (b as f32 - a as f32) * t) as u8;
+    Some(Color::Rgb(lerp(a.0, b.0), lerp(a.1, b.1), lerp(a.2, b.2)))
+}
+
+/// Approximate RGB for a color. The default colors depend on the terminal
+/// theme, so they have none.
+fn rgb(color: Color) -> Option<(u8, u8, u8)> {
+    Some(match color {
+        Color::Reset => return None,
+        Color::Black => (0, 0, 0),
+        Color::Red => (128, 0, 0),
+        Color::Green => (0, 128, 0),
+        Color::Yellow => (128, 128, 0),
+        Color::Blue => (0, 0, 128),
+        Color::Magenta => (128, 0, 128),
+        Color::Cyan => (0, 128, 128),
+        Color::Gray => (192, 192, 192),
+        Color::DarkGray => (128, 128, 128),
+        Color::LightRed => (255, 0, 0),
+        Color::LightGreen => (0, 255, 0),
+        Color::LightYellow => (255, 255, 0),
+        Color::LightBlue => (0, 0, 255),
+        Color::LightMagenta => (255, 0, 255),
+        Color::LightCyan => (0, 255, 255),
+        Color::White => (255, 255, 255),
+        Color::Rgb(r, g, b) => (r, g, b),
+        Color::Indexed(i) => match i {
+            0..=15 => return rgb(ANSI[i as usize]),
+            16..=231 => {
+                let level = |v: u8| if v == 0 { 0 } else { 55 + v * 40 };
+                let i = i - 16;
+                (level(i / 36), level((i / 6) % 6), level(i % 6))
+            },
+            _ => {
+                let v = 8 + (i - 232) * 10;
+                (v, v, v)
+            },
+        },
+    })
+}
+
+const ANSI: [Color; 16] = [
+    Color::Black, Color::Red, Color::Green, Color::Yellow,
+    Color::Blue, Color::Magenta, Color::Cyan, Color::Gray,
+    Color::DarkGray, Color::LightRed, Color::LightGreen, Color::LightYellow,
+    Color::LightBlue, Color::LightMagenta, Color::LightCyan, Color::White,
+];
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ratatui::layout::Rect;
+
+    fn cell(symbol: &str, fg: Color, bg: Color) -> Cell {
+        let mut cell = Cell::default();
+        cell.set_symbol(symbol).set_fg(fg).set_bg(bg);
+        cell
+    }
+
+    #[test]
+    fn transparent_cells_let_what_is_beneath_through() {
+        let lower = cell("a", Color::Red, Color::Blue);
+        let mut drawn = lower.clone();
+        over(&transparent_cell(), &mut drawn, 1.0);
+        assert_eq!(drawn, lower);
+
+        // A background alone tints what's beneath
+        let tint = cell(TRANSPARENT, Color::Reset, Color::Green);
+        over(&tint, &mut drawn, 1.0);
+        assert_eq!(drawn, cell("a", Color::Red, Color::Green));
+    }
+
+    #[test]
+    fn canonical_cells_follow_the_blend() {
+        let filled = cell(TRANSPARENT, Color::Reset, Color::Green);
+        assert_eq!(canonical(&filled, Blend::Normal).symbol(), " ");
+        assert_eq!(canonical(&transparent_cell(), Blend::Normal), transparent_cell());
+
+        let drawn = cell("x", Color::Red, Color::Green);
+        assert_eq!(canonical(&drawn, Blend::Foreground), cell("x", Color::Red, Color::Reset));
+        assert_eq!(canonical(&drawn, Blend::Background), cell(TRANSPARENT, Color::Red, Color::Green));
+    }
+
+    #[test]
+    fn opacity_mixes_known_colors() {
+        let mut lower = cell("a", Color::White, Color::Rgb(0, 0, 0));
+        over(&cell("b", Color::Rgb(200, 100, 0), Color::Rgb(100, 0, 200)), &mut lower, 0.5);
+        assert_eq!(lower, cell("b", Color::Rgb(100, 50, 0), Color::Rgb(50, 0, 100)));
+
+        // Unknown colors can't be mixed, so the more opaque side wins
+        let mut lower = cell("a", Color::White, Color::Reset);
+        over(&cell("b", Color::Red, Color::Blue), &mut lower, 0.25);
+        assert_eq!(lower, cell("a", Color::White, Color::Reset));
+        over(&cell("b", Color::Red, Color::Blue), &mut lower, 0.75);
+        assert_eq!(lower, cell("b", Color::Red, Color::Blue));
+    }
+
+    #[test]
+    fn mix_clamps_and_needs_known_colors() {
+        let (black, white) = (Color::Rgb(0, 0, 0), Color::Rgb(255, 255, 255));
+        assert_eq!(mix(black, white, 0.0), Some(black));
+        assert_eq!(mix(black, white, 2.0), Some(white));
+        assert_eq!(mix(Color::Black, Color::White, 0.5), Some(Color::Rgb(127, 127, 127)));
+        assert_eq!(mix(Color::Indexed(196), black, 0.0), Some(Color::Rgb(255, 0, 0)));
+        assert_eq!(mix(Color::Reset, white, 0.5), None);
+    }
+
+    #[test]
+    fn dimming_darkens_default_colors_too() {
+        let mut buffer = Buffer::filled(Rect::new(0, 0, 2, 1), &cell("a", Color::Rgb(200, 100, 0), Color::Reset));
+        buffer.get_mut(1, 0).set_fg(Color::Reset).set_bg(Color::Rgb(0, 0, 100));
+        let defaults = DefaultColors { fg: Color::Rgb(100, 100, 100), bg: Color::Rgb(40, 40, 40) };
+        dim_buffer(&mut buffer, 0.5, &defaults);
+
+        assert_eq!(*buffer.get(0, 0), cell("a", Color::Rgb(100, 50, 0), Color::Rgb(20, 20, 20)));
+        assert_eq!(*buffer.get(1, 0), cell("a", Color::Rgb(50, 50, 50), Color::Rgb(0, 0, 50)));
+
+        // Without a color to darken, the terminal dims the text itself
+        let unknown = DefaultColors { fg: Color::Reset, bg: Color::Reset };
+        let mut buffer = Buffer::filled(Rect::new(0, 0, 1, 1), &cell("a", Color::Reset, Color::Reset));
+        dim_buffer(&mut buffer, 0.5, &unknown);
+        assert!(buffer.get(0, 0).modifier.contains(Modifier::DIM));
+        assert_eq!(buffer.get(0, 0).bg, Color::Reset);
+    }
+}
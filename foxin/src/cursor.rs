@@ -0,0 +1,176 @@
+use bevy::{
+    app::App,
+    ecs::{
+        change_detection::DetectChanges,
+        component::Component,
+        entity::Entity,
+        schedule::IntoSystemConfigs,
+        system::{IntoSystem, Query, Res, ResMut, Resource},
+    },
+    math::U16Vec2,
+};
+use crossterm::cursor::SetCursorStyle;
+use ratatui::{backend::Backend, layout::Rect};
+use crate::{
+    error::{FoxinError, report},
+    min_size::MinTerminalSize,
+    render::{DrawArea, DrawFrame, RenderAppExt, Terminal},
+    time::{Clock, RenderTimeout},
+    visibility::{self, Visibility},
+};
+
+pub(crate) fn build(app: &mut App, _: &crate::Foxin) {
+    app.init_resource::<FocusedEntity>();
+    app.init_resource::<PlacedCursor>();
+    app.redraw_on_change::<CursorRequest>();
+    app.add_systems(crate::schedule::PostLogic, redraw_on_focus_change);
+    app.add_systems(crate::schedule::PostRender, place_cursor
+        .pipe(report)
+        .after(DrawFrame)
+    );
+}
+
+pub(crate) fn cleanup(_: &mut App) {}
+
+/// The entity that gets the terminal cursor, if it has a [`CursorRequest`]
+#[derive(Resource, Default, Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FocusedEntity(pub Option<Entity>);
+
+/// Show the terminal cursor at `position` within the entity's [`DrawArea`]
+/// while it's the [`FocusedEntity`]. The cursor is hidden otherwise, or if
+/// `position` is outside the area.
+#[derive(Component, Default, Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CursorRequest {
+    pub position: U16Vec2,
+    pub shape: CursorShape,
+    pub blinking: bool,
+}
+
+#[derive(Default, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CursorShape {
+    /// Whatever the user's terminal is configured with
+    #[default]
+    Default,
+    Block,
+    Underline,
+    Bar,
+}
+
+impl CursorRequest {
+    pub fn at(position: U16Vec2) -> Self {
+        Self {
+            position,
+            ..Default::default()
+        }
+    }
+
+    /// Where in the terminal the cursor goes, if `position` is within `area`
+    fn within(&self, area: Rect) -> Option<U16Vec2> {
+        let inside = self.position.x < area.width && self.position.y < area.height;
+        inside.then(|| U16Vec2::new(area.x, area.y) + self.position)
+    }
+
+    fn style(&self) -> SetCursorStyle {
+        match (self.shape, self.blinking) {
+            (CursorShape::Default, _) => SetCursorStyle::DefaultUserShape,
+            (CursorShape::Block, true) => SetCursorStyle::BlinkingBlock,
+            (CursorShape::Block, false) => SetCursorStyle::SteadyBlock,
+            (CursorShape::Underline, true) => SetCursorStyle::BlinkingUnderScore,
+            (CursorShape::Underline, false) => SetCursorStyle::SteadyUnderScore,
+            (CursorShape::Bar, true) => SetCursorStyle::BlinkingBar,
+            (CursorShape::Bar, false) => SetCursorStyle::SteadyBar,
+        }
+    }
+}
+
+fn redraw_on_focus_change(
+    focused: Res<FocusedEntity>,
+    mut timeout: ResMut<RenderTimeout>,
+    clock: Res<Clock>,
+) {
+    if focused.is_changed() && !focused.is_added() {
+        timeout.by(clock.now());
+    }
+}
+
+/// What [`place_cursor`] last did with the terminal cursor
+#[derive(Resource, Default, Debug)]
+pub(crate) struct PlacedCursor {
+    /// Where it was shown, or `None` if hidden
+    pub(crate) at: Option<U16Vec2>,
+    shape: Option<(CursorShape, bool)>,
+}
+
+/// Drawing moves the cursor, so it's put back after every frame
+fn place_cursor(
+    mut terminal: ResMut<Terminal>,
+    focused: Res<FocusedEntity>,
+    min_size: Res<MinTerminalSize>,
+    requests: Query<(&CursorRequest, &DrawArea, Option<&Visibility>)>,
+    mut placed: ResMut<PlacedCursor>,
+) -> Result<(), FoxinError> {
+    let size = terminal.0.get_frame().size();
+    let request = focused.0
+        .and_then(|entity| requests.get(entity).ok())
+        .filter(|(_, _, v)| !visibility::is_hidden(*v))
+        .filter(|_| !min_size.too_small(size))
+        .and_then(|(request, area, _)| Some((request, request.within(area.0)?)));
+
+    let Some((request, at)) = request else {
+        placed.at = None;
+        return terminal.0.hide_cursor().map_err(FoxinError::Terminal);
+    };
+
+    terminal.0.set_cursor(at.x, at.y).map_err(FoxinError::Terminal)?;
+    placed.at = Some(at);
+    if placed.shape != Some((request.shape, request.blinking)) {
+        placed.shape = Some((request.shape, request.blinking));
+        terminal.0.backend_mut().queue(request.style()).map_err(FoxinError::Terminal)?;
+    }
+    terminal.0.show_cursor().map_err(FoxinError::Terminal)?;
+    terminal.0.backend_mut().flush().map_err(FoxinError::Terminal)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bevy::hierarchy::BuildWorldChildren;
+    use ratatui::widgets::Block;
+    use crate::{frame::Frame, render::{tests::{headless_app, request_frame}, Layer}};
+
+    fn placed(app: &mut App) -> Option<U16Vec2> {
+        request_frame(app);
+        let at = app.world.resource::<PlacedCursor>().at;
+        let terminal = &mut app.world.resource_mut::<Terminal>().0;
+        if let Some(at) = at {
+            assert_eq!(terminal.backend_mut().get_cursor().unwrap(), (at.x, at.y));
+        }
+        at
+    }
+
+    #[test]
+    fn the_cursor_follows_the_focused_entity() {
+        let mut app = headless_app(U16Vec2::new(10, 5));
+        let mut entity = None;
+        app.world
+            .spawn((Layer(0), Frame(Block::bordered())))
+            .with_children(|frame| {
+                entity = Some(frame.spawn((CursorRequest::at(U16Vec2::new(3, 1)), DrawArea::default())).id());
+            });
+        let entity = entity.unwrap();
+        assert_eq!(placed(&mut app), None);
+        assert_eq!(app.world.get::<DrawArea>(entity).unwrap().0, Rect::new(1, 1, 8, 3));
+
+        app.world.resource_mut::<FocusedEntity>().0 = Some(entity);
+        assert_eq!(placed(&mut app), Some(U16Vec2::new(4, 2)));
+
+        // Outside its area it would be over something else
+        app.world.get_mut::<CursorRequest>(entity).unwrap().position = U16Vec2::new(8, 0);
+        assert_eq!(placed(&mut app), None);
+        app.world.get_mut::<CursorRequest>(entity).unwrap().position = U16Vec2::new(7, 2);
+        assert_eq!(placed(&mut app), Some(U16Vec2::new(8, 3)));
+
+        app.world.resource_mut::<FocusedEntity>().0 = None;
+        assert_eq!(placed(&mut app), None);
+    }
+}
@@ -0,0 +1,215 @@
+use bevy::{
+    app::{App, Startup},
+    ecs::{
+        component::Component,
+        query::With,
+        schedule::ScheduleLabel,
+        system::{Commands, Query, Res, Resource},
+        world::World,
+    },
+    hierarchy::BuildChildren,
+    math::U16Vec2,
+};
+use crossterm::event::KeyCode;
+use ratatui::{
+    style::{Color, Style},
+    widgets::{Block, Paragraph},
+};
+use std::{
+    collections::VecDeque,
+    time::{Duration, Instant},
+};
+use crate::{
+    float::{Anchor, Floating},
+    frame::Frame,
+    render::{FoxinWidget, Layer},
+    time::MaxRenderFrequency,
+    visibility::{self, ToggleKey, Visibility},
+};
+
+const OVERLAY_SIZE: U16Vec2 = U16Vec2::new(24, 14);
+
+pub(crate) fn build(app: &mut App, foxin: &crate::Foxin) {
+    app.init_resource::<FrameDiagnostics>();
+    if let Some(key) = foxin.diagnostics_overlay {
+        app.insert_resource(OverlayKey(key));
+        app.add_systems(Startup, spawn_overlay);
+        app.add_systems(crate::schedule::MidRender, update_overlay);
+    }
+}
+
+pub(crate) fn cleanup(_: &mut App) {}
+
+/// How long the last frame took, for tuning
+/// [`MaxRenderFrequency`](crate::time::MaxRenderFrequency). These are real
+/// times whatever the [`Clock`](crate::time::Clock) says.
+#[derive(Resource, Debug, Default, Clone)]
+pub struct FrameDiagnostics {
+    /// Render schedules keep their times from the last frame that was drawn
+    pub schedules: ScheduleTimes,
+    /// How long the runner could wait for input after the last frame
+    pub max_sleep: Duration,
+    /// Written to the terminal by the last frame that was drawn
+    pub bytes_written: usize,
+    /// Frames drawn in the last second
+    pub fps: f32,
+    drawn: VecDeque<Instant>,
+}
+
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq)]
+pub struct ScheduleTimes {
+    pub pre_logic: Duration,
+    pub logic: Duration,
+    pub post_logic: Duration,
+    pub pre_layout: Duration,
+    pub layout: Duration,
+    pub mid_render: Duration,
+    pub render: Duration,
+    pub post_render: Duration,
+}
+
+impl ScheduleTimes {
+    pub fn iter(&self) -> impl Iterator<Item = (&'static str, Duration)> {
+        [
+            ("PreLogic", self.pre_logic),
+            ("Logic", self.logic),
+            ("PostLogic", self.post_logic),
+            ("PreLayout", self.pre_layout),
+            ("Layout", self.layout),
+            ("MidRender", self.mid_render),
+            ("Render", self.render),
+            ("PostRender", self.post_render),
+        ].into_iter()
+    }
+
+    pub fn total(&self) -> Duration {
+        self.iter().map(|(_, time)| time).sum()
+    }
+}
+
+/// Run a schedule and record how long it took
+pub(crate) fn run_timed(
+    world: &mut World,
+    label: impl ScheduleLabel,
+    time: fn(&mut ScheduleTimes) -> &mut Duration,
+) {
+    let start = Instant::now();
+    world.run_schedule(label);
+    let elapsed = start.elapsed();
+    *time(&mut world.resource_mut::<FrameDiagnostics>().schedules) = elapsed;
+}
+
+/// Called before the render schedules run
+pub(crate) fn start_render(world: &mut World) {
+    // Anything written since the last frame wasn't part of drawing this one
+    crate::render::take_bytes_written(world);
+    let mut diagnostics = world.resource_mut::<FrameDiagnostics>();
+    let times = &mut diagnostics.schedules;
+    times.layout = Duration::ZERO;
+    times.mid_render = Duration::ZERO;
+    times.render = Duration::ZERO;
+}
+
+/// Called after the render schedules ran
+pub(crate) fn end_render(world: &mut World) {
+    let now = Instant::now();
+    let bytes_written = crate::render::take_bytes_written(world);
+    let mut diagnostics = world.resource_mut::<FrameDiagnostics>();
+    diagnostics.bytes_written = bytes_written;
+    diagnostics.drawn.push_back(now);
+    while diagnostics.drawn.front().is_some_and(|t| now - *t > Duration::from_secs(1)) {
+        diagnostics.drawn.pop_front();
+    }
+    diagnostics.fps = diagnostics.drawn.len() as f32;
+}
+
+#[derive(Resource)]
+struct OverlayKey(KeyCode);
+
+#[derive(Component)]
+struct OverlayRoot;
+
+#[derive(Component)]
+struct OverlayText;
+
+fn spawn_overlay(mut commands: Commands, key: Res<OverlayKey>) {
+    let style = Style::default().fg(Color::White).bg(Color::Black);
+    commands
+        .spawn((Layer(usize::MAX - 1), Visibility::Hidden, ToggleKey::new(key.0), OverlayRoot))
+        .with_children(|root| {
+            root.spawn((
+                Floating::new(Anchor::TopRight, OVERLAY_SIZE),
+                Frame(Block::bordered().title(" Frame ").style(style)),
+                FoxinWidget::new(Paragraph::default()),
+                OverlayText,
+            ));
+        });
+}
+
+/// Shows the numbers from the frame before, since this one isn't done yet.
+/// Changing the text here doesn't ask for another frame, so the overlay
+/// doesn't keep an idle game drawing.
+fn update_overlay(
+    diagnostics: Res<FrameDiagnostics>,
+    max_freq: Res<MaxRenderFrequency>,
+    roots: Query<&Visibility, With<OverlayRoot>>,
+    mut texts: Query<&mut FoxinWidget, With<OverlayText>>,
+) {
+    if roots.iter().all(|v| visibility::is_hidden(Some(v))) {
+        return;
+    }
+    let ms = |time: Duration| format!("{:>8.2}ms", time.as_secs_f64() * 1000.0);
+    let mut lines = vec![format!("FPS {:>10.0} / {:.0}", diagnostics.fps, max_freq.0)];
+    lines.extend(diagnostics.schedules.iter().map(|(name, time)| format!("{name:<10}{}", ms(time))));
+    lines.push(format!("{:<10}{}", "Total", ms(diagnostics.schedules.total())));
+    lines.push(format!("{:<10}{}", "Sleep", ms(diagnostics.max_sleep)));
+    lines.push(format!("{:<10}{:>8} B", "Written", diagnostics.bytes_written));
+
+    let style = Style::default().fg(Color::White).bg(Color::Black);
+    for mut text in texts.iter_mut() {
+        *text = FoxinWidget::new(Paragraph::new(lines.join("\n")).style(style));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        input::{KeyModifiers, KeyPress},
+        render::tests::{headless_app_with, request_frame},
+    };
+
+    fn lines(app: &App) -> Vec<String> {
+        let buffer = crate::render::headless_buffer(&app.world).unwrap();
+        (0..buffer.area.height)
+            .map(|y| (0..buffer.area.width).map(|x| buffer.get(x, y).symbol()).collect::<String>())
+            .collect()
+    }
+
+    fn toggle(app: &mut App) {
+        app.world.send_event(KeyPress { code: KeyCode::F(3), modifiers: KeyModifiers::NONE, repeat: false });
+        app.update();
+    }
+
+    #[test]
+    fn the_overlay_shows_timings_only_when_toggled_on() {
+        let foxin = crate::Foxin::new().diagnostics_overlay(KeyCode::F(3));
+        let mut app = headless_app_with(foxin, OVERLAY_SIZE + U16Vec2::new(6, 0));
+        app.update();
+        assert!(lines(&app).iter().all(|line| line.trim().is_empty()));
+
+        toggle(&mut app);
+        request_frame(&mut app);
+        let shown = lines(&app);
+        assert!(shown[0].ends_with("┌ Frame ───────────────┐"), "{shown:#?}");
+        let row = |label: &str| shown.iter().find(|line| line.contains(label)).cloned();
+        assert!(row("FPS").is_some_and(|row| row.contains("/ 0")), "{shown:#?}");
+        assert!(row("Total").is_some_and(|row| row.contains("ms")), "{shown:#?}");
+        assert!(row("Written").is_some_and(|row| row.contains("0 B")), "{shown:#?}");
+        // It sits in the corner, leaving the rest alone
+        assert!(shown.iter().all(|line| line.starts_with("      ")));
+
+        toggle(&mut app);
+        assert!(lines(&app).iter().all(|line| line.trim().is_empty()));
+    }
+}
@@ -0,0 +1,150 @@
+use bevy::{
+    app::App,
+    ecs::{
+        system::{In, Resource, ResMut},
+        world::World,
+    },
+};
+use std::{
+    fmt,
+    io,
+    sync::{Mutex, Once},
+};
+
+static EXIT_ERROR: Mutex<Option<FoxinError>> = Mutex::new(None);
+static PANIC_HOOK: Once = Once::new();
+
+pub(crate) fn build(app: &mut App, foxin: &crate::Foxin) {
+    app.init_resource::<Failure>();
+
+    // Headless apps have no terminal to restore. Building more than one app
+    // that uses the terminal would otherwise chain a hook per app.
+    let mode = crate::render::OutputMode::of(foxin);
+    if !mode.headless {
+        install_panic_hook(move || crate::restore_terminal(mode));
+    }
+}
+
+/// Run `restore` before the panic message is printed. Only the first call
+/// installs anything.
+fn install_panic_hook(restore: impl Fn() + Send + Sync + 'static) {
+    PANIC_HOOK.call_once(|| {
+        let default_hook = std::panic::take_hook();
+        std::panic::set_hook(Box::new(move |info| {
+            restore();
+            default_hook(info);
+        }));
+    });
+}
+
+pub(crate) fn cleanup(app: &mut App) {
+    if let Some(err) = app.world.get_resource_mut::<Failure>().and_then(|mut f| f.0.take()) {
+        *EXIT_ERROR.lock().unwrap_or_else(|e| e.into_inner()) = Some(err);
+    }
+}
+
+/// The error that stopped the runner, if any. Call after `App::run` returns.
+pub fn take_exit_error() -> Option<FoxinError> {
+    EXIT_ERROR.lock().unwrap_or_else(|e| e.into_inner()).take()
+}
+
+#[derive(Debug)]
+pub enum FoxinError {
+    Terminal(io::Error),
+    Recording(io::Error),
+    Playback(io::Error),
+}
+
+impl fmt::Display for FoxinError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Terminal(err) => write!(f, "terminal I/O failed: {}", err),
+            Self::Recording(err) => write!(f, "writing input recording failed: {}", err),
+            Self::Playback(err) => write!(f, "reading input recording failed: {}", err),
+        }
+    }
+}
+
+impl std::error::Error for FoxinError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Self::Terminal(err) | Self::Recording(err) | Self::Playback(err) => Some(err),
+        }
+    }
+}
+
+/// Holds the first error foxin couldn't recover from. The runner stops at
+/// the end of the frame it was set in.
+#[derive(Resource, Default, Debug)]
+pub struct Failure(Option<FoxinError>);
+
+impl Failure {
+    pub fn error(&self) -> Option<&FoxinError> {
+        self.0.as_ref()
+    }
+
+    pub fn set(&mut self, err: FoxinError) {
+        if self.0.is_none() {
+            self.0 = Some(err);
+        }
+    }
+}
+
+pub(crate) fn failed(world: &World) -> bool {
+    world
+        .get_resource::<Failure>()
+        .map(|failure| failure.0.is_some())
+        .unwrap_or(false)
+}
+
+pub(crate) fn fail(world: &mut World, err: FoxinError) {
+    world.get_resource_or_insert_with(Failure::default).set(err);
+}
+
+/// Pipe target for systems that can fail
+pub(crate) fn report(
+    In(result): In<Result<(), FoxinError>>,
+    mut failure: ResMut<Failure>,
+) {
+    if let Err(err) = result {
+        failure.set(err);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bevy::{app::First, math::U16Vec2};
+    use std::sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    };
+    use crate::render::{tests::headless_app, FoxinBackend, Terminal};
+
+    #[test]
+    fn failed_writes_stop_the_runner() {
+        let mut app = headless_app(U16Vec2::new(4, 1));
+        app.update();
+        *app.world.resource_mut::<Terminal>().0.backend_mut() = FoxinBackend::Failing;
+        let updates = Arc::new(AtomicUsize::new(0));
+        let counted = updates.clone();
+        app.add_systems(First, move || { counted.fetch_add(1, Ordering::Relaxed); });
+        let now = app.world.resource::<crate::time::Clock>().now();
+        app.world.resource_mut::<crate::time::RenderTimeout>().by(now);
+
+        crate::runner(app);
+        assert_eq!(updates.load(Ordering::Relaxed), 1);
+        assert!(matches!(take_exit_error(), Some(FoxinError::Terminal(_))));
+    }
+
+    #[test]
+    fn only_the_first_panic_hook_is_installed() {
+        let restored = Arc::new(AtomicUsize::new(0));
+        for _ in 0..2 {
+            let restored = restored.clone();
+            install_panic_hook(move || { restored.fetch_add(1, Ordering::Relaxed); });
+        }
+        std::panic::catch_unwind(|| panic!("restores the terminal")).unwrap_err();
+        assert_eq!(restored.load(Ordering::Relaxed), 1);
+    }
+}
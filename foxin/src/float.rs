@@ -0,0 +1,203 @@
+use bevy::{
+    app::App,
+    ecs::component::Component,
+    math::U16Vec2,
+};
+use ratatui::layout::Rect;
+use crate::render::RenderAppExt;
+
+pub(crate) fn build(app: &mut App, _: &crate::Foxin) {
+    app.relayout_on_change::<Floating>()
+        .relayout_on_change::<SizeLimits>()
+        .relayout_on_change::<ZIndex>();
+}
+
+pub(crate) fn cleanup(_: &mut App) {}
+
+/// Takes an entity out of its parent's [`Layout`](crate::render::Layout) and
+/// places it over its siblings instead
+#[derive(Component, Debug, Copy, Clone, PartialEq, Eq)]
+pub struct Floating {
+    pub anchor: Anchor,
+    pub size: U16Vec2,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Anchor {
+    Center,
+    TopLeft,
+    TopRight,
+    BottomLeft,
+    BottomRight,
+    /// Top left corner at this terminal position
+    At(U16Vec2),
+    /// Beside this terminal position, below and to the right unless that
+    /// doesn't fit, e.g. for a tooltip about a map cell. A game's own
+    /// coordinates, like a map's, have to be turned into terminal ones first,
+    /// relative to the [`DrawArea`](crate::render::DrawArea) they're drawn in.
+    Near(U16Vec2),
+}
+
+impl Floating {
+    pub fn new(anchor: Anchor, size: U16Vec2) -> Self {
+        Self { anchor, size }
+    }
+
+    /// Where this goes within `container`, which it never leaves
+    pub fn place(&self, container: Rect) -> Rect {
+        let width = self.size.x.min(container.width);
+        let height = self.size.y.min(container.height);
+        let max_x = container.right() - width;
+        let max_y = container.bottom() - height;
+
+        let (x, y) = match self.anchor {
+            Anchor::Center => (
+                container.x + (container.width - width) / 2,
+                container.y + (container.height - height) / 2,
+            ),
+            Anchor::TopLeft => (container.x, container.y),
+            Anchor::TopRight => (max_x, container.y),
+            Anchor::BottomLeft => (container.x, max_y),
+            Anchor::BottomRight => (max_x, max_y),
+            Anchor::At(pos) => (pos.x, pos.y),
+            Anchor::Near(pos) => (
+                beside(pos.x, width, container.x, container.right()),
+                beside(pos.y, height, container.y, container.bottom()),
+            ),
+        };
+
+        Rect {
+            x: x.clamp(container.x, max_x),
+            y: y.clamp(container.y, max_y),
+            width,
+            height,
+        }
+    }
+}
+
+/// After `pos` if there's room, otherwise before it
+fn beside(pos: u16, len: u16, min: u16, max: u16) -> u16 {
+    if pos as u32 + 1 + len as u32 <= max as u32 {
+        pos + 1
+    } else {
+        pos.saturating_sub(len).max(min)
+    }
+}
+
+/// Bounds on the size of an entity's area, whether it floats or not. Areas
+/// only grow into space their parent has: floating ones into the parent's
+/// area, and tiled ones within the slot its layout gives them, so they never
+/// overlap their siblings. Reserve room for a tiled minimum with its
+/// [`Constraint`](crate::render::Constraint).
+#[derive(Component, Debug, Copy, Clone, PartialEq, Eq)]
+pub struct SizeLimits {
+    pub min: U16Vec2,
+    pub max: U16Vec2,
+}
+
+impl Default for SizeLimits {
+    fn default() -> Self {
+        Self {
+            min: U16Vec2::ZERO,
+            max: U16Vec2::MAX,
+        }
+    }
+}
+
+impl SizeLimits {
+    pub(crate) fn apply(&self, area: Rect, container: Rect) -> Rect {
+        let width = area.width
+            .clamp(self.min.x, self.max.x.max(self.min.x))
+            .min(container.right().saturating_sub(area.x));
+        let height = area.height
+            .clamp(self.min.y, self.max.y.max(self.min.y))
+            .min(container.bottom().saturating_sub(area.y));
+        Rect { width, height, ..area }
+    }
+}
+
+/// Draw order within a layer. Higher is drawn later, over lower. Children
+/// are drawn relative to their parent, and floating entities over their
+/// siblings.
+#[derive(Component, Debug, Default, Copy, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub struct ZIndex(pub i32);
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CONTAINER: Rect = Rect { x: 5, y: 2, width: 20, height: 10 };
+
+    fn place(anchor: Anchor, size: (u16, u16)) -> Rect {
+        Floating::new(anchor, U16Vec2::new(size.0, size.1)).place(CONTAINER)
+    }
+
+    #[test]
+    fn floating_entities_stay_in_their_container() {
+        assert_eq!(place(Anchor::TopRight, (8, 3)), Rect::new(17, 2, 8, 3));
+        assert_eq!(place(Anchor::BottomLeft, (8, 3)), Rect::new(5, 9, 8, 3));
+        assert_eq!(place(Anchor::Center, (9, 3)), Rect::new(10, 5, 9, 3));
+        assert_eq!(place(Anchor::At(U16Vec2::new(0, 0)), (4, 4)), Rect::new(5, 2, 4, 4));
+        assert_eq!(place(Anchor::At(U16Vec2::new(23, 11)), (4, 4)), Rect::new(21, 8, 4, 4));
+        // Too big to fit, so it fills the container
+        assert_eq!(place(Anchor::BottomRight, (30, 12)), CONTAINER);
+    }
+
+    #[test]
+    fn near_flips_at_the_edges() {
+        assert_eq!(place(Anchor::Near(U16Vec2::new(8, 4)), (6, 3)), Rect::new(9, 5, 6, 3));
+        // Against the right and bottom edges it goes above and to the left
+        assert_eq!(place(Anchor::Near(U16Vec2::new(24, 11)), (6, 3)), Rect::new(18, 8, 6, 3));
+        assert_eq!(place(Anchor::Near(U16Vec2::new(20, 4)), (6, 3)), Rect::new(14, 5, 6, 3));
+        // No room on either side, so it covers the position rather than leave
+        assert_eq!(place(Anchor::Near(U16Vec2::new(12, 6)), (15, 8)), Rect::new(5, 2, 15, 8));
+        assert_eq!(place(Anchor::Near(U16Vec2::new(0, 0)), (6, 3)), Rect::new(5, 2, 6, 3));
+    }
+
+    #[test]
+    fn beside_prefers_after() {
+        assert_eq!(beside(3, 4, 0, 10), 4);
+        assert_eq!(beside(5, 4, 0, 10), 6);
+        assert_eq!(beside(6, 4, 0, 10), 2);
+        assert_eq!(beside(2, 4, 1, 6), 1);
+        assert_eq!(beside(u16::MAX, 4, 0, u16::MAX), u16::MAX - 4);
+    }
+
+    #[test]
+    fn size_limits_grow_only_into_the_container() {
+        let limits = SizeLimits { min: U16Vec2::new(6, 4), max: U16Vec2::new(10, 5) };
+        assert_eq!(limits.apply(Rect::new(5, 2, 3, 2), CONTAINER), Rect::new(5, 2, 6, 4));
+        assert_eq!(limits.apply(Rect::new(5, 2, 20, 10), CONTAINER), Rect::new(5, 2, 10, 5));
+        // Near the edge there's only so much room
+        assert_eq!(limits.apply(Rect::new(22, 10, 3, 2), CONTAINER), Rect::new(22, 10, 3, 2));
+        // A max below the min gives way to it
+        let crossed = SizeLimits { min: U16Vec2::new(6, 4), max: U16Vec2::new(2, 2) };
+        assert_eq!(crossed.apply(Rect::new(5, 2, 20, 10), CONTAINER), Rect::new(5, 2, 6, 4));
+    }
+
+    #[test]
+    fn tiled_limits_keep_to_their_slot() {
+        use bevy::hierarchy::BuildWorldChildren;
+        use ratatui::layout::{Constraint as Size, Direction, Layout as Split};
+        use crate::render::{tests::headless_app, Constraint, DrawArea, Layer, Layout};
+
+        let mut app = headless_app(U16Vec2::new(12, 4));
+        let mut tiled = Vec::new();
+        app.world
+            .spawn((Layer(0), Layout(Split::default().direction(Direction::Horizontal))))
+            .with_children(|root| {
+                let limits = [
+                    SizeLimits { min: U16Vec2::new(8, 6), ..Default::default() },
+                    SizeLimits { max: U16Vec2::new(3, 2), ..Default::default() },
+                ];
+                for limits in limits {
+                    tiled.push(root.spawn((Constraint(Size::Length(6)), limits, DrawArea::default())).id());
+                }
+            });
+        app.update();
+
+        let area = |entity| app.world.get::<DrawArea>(entity).unwrap().0;
+        assert_eq!(area(tiled[0]), Rect::new(0, 0, 6, 4));
+        assert_eq!(area(tiled[1]), Rect::new(6, 0, 3, 2));
+    }
+}
@@ -0,0 +1,274 @@
+use bevy::{
+    app::{App, First},
+    ecs::{
+        entity::Entity,
+        schedule::IntoSystemConfigs,
+        system::{Commands, Query, Res, ResMut, Resource},
+        world::World,
+    },
+    hierarchy::{BuildChildren, DespawnRecursiveExt},
+    math::U16Vec2,
+};
+use ratatui::{
+    style::{Color, Modifier, Style},
+    widgets::Paragraph,
+};
+use crate::{
+    Foxin,
+    animate::Animation,
+    blend::Dim,
+    float::{Anchor, Floating},
+    input::{GatherInput, TerminalFocus},
+    render::{DrawFrame, FoxinWidget, Layer},
+    schedule::PostRender,
+    time::{Clock, LogicTimeout, MaxRenderFrequency, RenderTimeout},
+    timer::Timers,
+};
+use std::time::Instant;
+
+const PAUSED_TEXT: &str = " PAUSED ";
+
+pub(crate) fn build(app: &mut App, foxin: &Foxin) {
+    app.init_resource::<Paused>();
+    if let Some(policy) = &foxin.pause_on_focus_loss {
+        app.insert_resource(policy.clone());
+        app.add_systems(First, pause_on_focus_change.after(GatherInput));
+        app.add_systems(PostRender, slow_down_once_drawn.after(DrawFrame));
+    }
+}
+
+pub(crate) fn cleanup(_: &mut App) {}
+
+/// What to do while the terminal doesn't have focus
+#[derive(Resource, Debug, Clone)]
+pub struct FocusPolicy {
+    pub max_render_frequency: f32,
+    pub show_overlay: bool,
+}
+
+impl Default for FocusPolicy {
+    fn default() -> Self {
+        Self {
+            max_render_frequency: 1.0,
+            show_overlay: true,
+        }
+    }
+}
+
+/// Set while the game is paused because the terminal lost focus. Whatever
+/// runs on the [`Clock`] holds still: [`Timers`] don't fire, [`Animation`]s
+/// don't move and the [`LogicTimeout`] doesn't pass, and on resuming they
+/// move back by however long the pause lasted. `Logic` still runs, so input
+/// and playback are handled as usual.
+#[derive(Resource, Default, Debug)]
+pub struct Paused {
+    since: Option<Instant>,
+    saved_frequency: f32,
+    overlay: Option<Entity>,
+    /// Set until the frame showing the pause has been drawn
+    slow_down: bool,
+}
+
+impl Paused {
+    pub fn is_paused(&self) -> bool {
+        self.since.is_some()
+    }
+}
+
+/// Run condition for systems that should hold still while [`Paused`]
+pub fn not_paused(paused: Res<Paused>) -> bool {
+    !paused.is_paused()
+}
+
+pub(crate) fn is_paused(world: &World) -> bool {
+    world
+        .get_resource::<Paused>()
+        .is_some_and(Paused::is_paused)
+}
+
+#[allow(clippy::too_many_arguments)]
+fn pause_on_focus_change(
+    mut commands: Commands,
+    focus: Res<TerminalFocus>,
+    policy: Res<FocusPolicy>,
+    mut paused: ResMut<Paused>,
+    mut max_freq: ResMut<MaxRenderFrequency>,
+    mut render_timeout: ResMut<RenderTimeout>,
+    mut logic_timeout: ResMut<LogicTimeout>,
+    mut timers: ResMut<Timers>,
+    mut animations: Query<&mut Animation>,
+    clock: Res<Clock>,
+) {
+    if !focus.focus_changed || focus.focused != paused.is_paused() {
+        return;
+    }
+
+    if focus.focused {
+        if let Some(since) = paused.since.take() {
+            let paused_for = clock.now().saturating_duration_since(since);
+            logic_timeout.delay(paused_for);
+            timers.delay(paused_for);
+            for mut animation in animations.iter_mut() {
+                animation.started += paused_for;
+            }
+        }
+        // The game may have picked its own frequency in the meantime
+        if !std::mem::take(&mut paused.slow_down) && max_freq.0 == policy.max_render_frequency {
+            max_freq.0 = paused.saved_frequency;
+        }
+        if let Some(overlay) = paused.overlay.take() {
+            commands.entity(overlay).despawn_recursive();
+        }
+    } else {
+        paused.since = Some(clock.now());
+        paused.saved_frequency = max_freq.0;
+        paused.slow_down = true;
+        if policy.show_overlay {
+            paused.overlay = Some(spawn_overlay(&mut commands));
+        }
+    }
+
+    render_timeout.by(clock.now());
+}
+
+/// Lowering the frequency straight away could hold back the frame that
+/// shows the game is paused
+fn slow_down_once_drawn(
+    policy: Res<FocusPolicy>,
+    mut paused: ResMut<Paused>,
+    mut max_freq: ResMut<MaxRenderFrequency>,
+) {
+    if std::mem::take(&mut paused.slow_down) {
+        max_freq.0 = policy.max_render_frequency;
+    }
+}
+
+fn spawn_overlay(commands: &mut Commands) -> Entity {
+    commands
+        .spawn((Layer(usize::MAX), Dim(0.5)))
+        .with_children(|root| {
+            root.spawn((
+                Floating::new(Anchor::Center, U16Vec2::new(PAUSED_TEXT.len() as u16, 1)),
+                FoxinWidget::new(Paragraph::new(PAUSED_TEXT).style(
+                    Style::default()
+                        .fg(Color::Black)
+                        .bg(Color::Yellow)
+                        .add_modifier(Modifier::BOLD)
+                )),
+            ));
+        })
+        .id()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bevy::ecs::{
+        event::{Event, Events},
+        schedule::Schedule,
+    };
+    use std::time::Duration;
+
+    #[derive(Event, Clone)]
+    struct Tick;
+
+    fn world() -> World {
+        let mut world = World::new();
+        world.insert_resource(Clock::manual());
+        world.insert_resource(FocusPolicy::default());
+        world.insert_resource(MaxRenderFrequency(30.0));
+        world.init_resource::<TerminalFocus>();
+        world.init_resource::<Paused>();
+        world.init_resource::<RenderTimeout>();
+        world.init_resource::<LogicTimeout>();
+        world.init_resource::<Timers>();
+        world.init_resource::<Events<Tick>>();
+        world
+    }
+
+    fn set_focus(world: &mut World, focused: bool) {
+        *world.resource_mut::<TerminalFocus>() = TerminalFocus { focused, focus_changed: true };
+        Schedule::default()
+            .add_systems((pause_on_focus_change, slow_down_once_drawn).chain())
+            .run(world);
+        world.resource_mut::<TerminalFocus>().focus_changed = false;
+    }
+
+    fn ticks(world: &mut World) -> usize {
+        crate::timer::fire_timers(world);
+        world.resource_mut::<Events<Tick>>().drain().count()
+    }
+
+    #[test]
+    fn pausing_holds_time_still() {
+        let mut world = world();
+        let step = Duration::from_millis(100);
+        let start = world.resource::<Clock>().now();
+        world.resource_mut::<Timers>().repeating(start + step, step, Tick);
+        let animation = world.spawn(Animation::repeating(start, step)).id();
+
+        world.resource_mut::<Clock>().advance(step);
+        assert_eq!(ticks(&mut world), 1);
+
+        set_focus(&mut world, false);
+        assert!(world.resource::<Paused>().is_paused());
+        assert_eq!(world.resource::<MaxRenderFrequency>().0, 1.0);
+        world.resource_mut::<Clock>().advance(step * 50);
+        assert_eq!(ticks(&mut world), 0);
+
+        set_focus(&mut world, true);
+        assert!(!world.resource::<Paused>().is_paused());
+        assert_eq!(world.resource::<MaxRenderFrequency>().0, 30.0);
+        // The timer picks up where it left off rather than catching up
+        assert_eq!(world.resource::<Timers>().next_deadline(), Some(start + step * 52));
+        assert_eq!(ticks(&mut world), 0);
+        world.resource_mut::<Clock>().advance(step);
+        assert_eq!(ticks(&mut world), 1);
+        let now = world.resource::<Clock>().now();
+        assert_eq!(world.get::<Animation>(animation).unwrap().frame(now), 2);
+    }
+
+    #[test]
+    fn frequency_set_while_paused_is_kept() {
+        let mut world = world();
+
+        set_focus(&mut world, false);
+        world.resource_mut::<MaxRenderFrequency>().0 = 60.0;
+        set_focus(&mut world, true);
+        assert_eq!(world.resource::<MaxRenderFrequency>().0, 60.0);
+    }
+
+    #[derive(Resource, Default)]
+    struct LogicRuns(usize);
+
+    #[derive(Resource, Default)]
+    struct LoseFocus(bool);
+
+    #[test]
+    fn logic_runs_and_the_pause_is_drawn_before_slowing_down() {
+        use crate::render::tests::headless_app_with;
+
+        let foxin = Foxin::new().pause_on_focus_loss(FocusPolicy::default());
+        let mut app = headless_app_with(foxin, U16Vec2::new(10, 1));
+        app.init_resource::<LogicRuns>();
+        app.init_resource::<LoseFocus>();
+        app.add_systems(crate::schedule::Logic, |mut runs: ResMut<LogicRuns>| runs.0 += 1);
+        app.add_systems(First, (|mut focus: ResMut<TerminalFocus>, lose: Res<LoseFocus>| {
+            if lose.0 {
+                *focus = TerminalFocus { focused: false, focus_changed: true };
+            }
+        }).after(GatherInput).before(pause_on_focus_change));
+        app.update();
+
+        // Drawn straight after the last frame, which a lowered frequency
+        // would have held back
+        app.world.resource_mut::<LoseFocus>().0 = true;
+        app.update();
+        assert!(app.world.resource::<Paused>().is_paused());
+        assert_eq!(app.world.resource::<LogicRuns>().0, 2);
+        let buffer = crate::render::headless_buffer(&app.world).unwrap();
+        let text = buffer.content.iter().map(|cell| cell.symbol()).collect::<String>();
+        assert_eq!(text, "  PAUSED  ");
+        assert_eq!(app.world.resource::<MaxRenderFrequency>().0, 1.0);
+    }
+}
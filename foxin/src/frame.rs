@@ -0,0 +1,100 @@
+use bevy::{
+    app::App,
+    ecs::{
+        change_detection::{DetectChanges, Ref},
+        component::Component,
+        entity::Entity,
+        query::{With, Without},
+        schedule::IntoSystemConfigs,
+        system::{Commands, Query},
+    },
+};
+use ratatui::{
+    buffer::Buffer,
+    widgets::{Block, WidgetRef},
+};
+use crate::render::{RenderAppExt, RenderWidgets};
+
+pub(crate) fn build(app: &mut App, _: &crate::Foxin) {
+    app.relayout_on_change::<Frame>();
+    app.add_systems(crate::schedule::PreLayout, add_frame_buffers);
+    app.add_systems(crate::schedule::Render, render_frames.in_set(RenderWidgets));
+}
+
+pub(crate) fn cleanup(_: &mut App) {}
+
+/// Borders, titles and padding drawn around an entity. Its
+/// [`DrawArea`](crate::render::DrawArea), [`DrawBuffer`](crate::render::DrawBuffer)
+/// and children get what's left inside.
+#[derive(Component, Clone)]
+pub struct Frame(pub Block<'static>);
+
+/// What foxin drew for an entity's [`Frame`], covering its whole area
+#[derive(Component, Default)]
+pub(crate) struct FrameBuffer(pub(crate) Buffer);
+
+fn add_frame_buffers(
+    mut commands: Commands,
+    frames: Query<Entity, (With<Frame>, Without<FrameBuffer>)>,
+) {
+    for entity in frames.iter() {
+        commands.entity(entity).insert(FrameBuffer::default());
+    }
+}
+
+fn render_frames(
+    mut frames: Query<(Ref<Frame>, &mut FrameBuffer)>,
+) {
+    for (frame, mut buffer) in frames.iter_mut() {
+        // Layout marks the buffer changed when it resizes it
+        if !frame.is_changed() && !buffer.is_changed() {
+            continue;
+        }
+        let area = buffer.0.area;
+        buffer.0 = Buffer::filled(area, &crate::blend::transparent_cell());
+        frame.0.render_ref(area, &mut buffer.0);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bevy::{hierarchy::BuildWorldChildren, math::U16Vec2};
+    use ratatui::{layout::Rect, widgets::Paragraph};
+    use crate::render::{self, tests::headless_app, DrawArea, FoxinWidget, Layer};
+
+    fn framed(size: U16Vec2) -> (bevy::app::App, Entity) {
+        let mut app = headless_app(size);
+        let mut child = None;
+        app.world
+            .spawn((Layer(0), Frame(Block::bordered().title("Hi"))))
+            .with_children(|frame| {
+                child = Some(frame.spawn((FoxinWidget::new(Paragraph::new("inside")), DrawArea::default())).id());
+            });
+        app.update();
+        (app, child.unwrap())
+    }
+
+    fn symbols(app: &bevy::app::App) -> Vec<String> {
+        let buffer = render::headless_buffer(&app.world).unwrap();
+        (0..buffer.area.height)
+            .map(|y| (0..buffer.area.width).map(|x| buffer.get(x, y).symbol()).collect())
+            .collect()
+    }
+
+    #[test]
+    fn children_go_inside_the_frame() {
+        let (app, child) = framed(U16Vec2::new(8, 3));
+        assert_eq!(symbols(&app), ["┌Hi────┐", "│inside│", "└──────┘"]);
+        assert_eq!(app.world.get::<DrawArea>(child).unwrap().0, Rect::new(1, 1, 6, 1));
+    }
+
+    #[test]
+    fn frames_too_small_for_their_borders_leave_nothing_inside() {
+        for size in [U16Vec2::new(1, 1), U16Vec2::new(2, 2), U16Vec2::new(8, 2)] {
+            let (app, child) = framed(size);
+            assert!(app.world.get::<DrawArea>(child).unwrap().0.is_empty(), "at {size}");
+            assert!(symbols(&app).concat().chars().all(|c| "┌┐└┘─│Hi".contains(c)), "at {size}");
+        }
+    }
+}
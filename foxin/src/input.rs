@@ -2,50 +2,167 @@ use bevy::{
     app::{App, First},
     ecs::{
         event::EventWriter,
-        system::{ResMut, Resource},
+        schedule::{IntoSystemConfigs, SystemSet},
+        system::{IntoSystem, Res, ResMut, Resource},
     },
-    math::U16Vec2,
+    math::{I16Vec2, U16Vec2},
 };
 use crossterm::{
     event::{
         Event, poll, read,
-        KeyEventKind,
+        KeyEvent, KeyEventKind, MouseEventKind,
+        KeyboardEnhancementFlags,
+        PushKeyboardEnhancementFlags, PopKeyboardEnhancementFlags,
         EnableFocusChange, DisableFocusChange,
         EnableMouseCapture, DisableMouseCapture,
         EnableBracketedPaste, DisableBracketedPaste,
     },
+    terminal::supports_keyboard_enhancement,
     ExecutableCommand,
 };
-use std::{time::Duration, io::stdout};
+use std::{
+    collections::HashMap,
+    time::{Duration, Instant},
+    io::{self, stdout},
+};
+use crate::{Foxin, error::FoxinError, time::{Clock, LogicTimeout}, quit::AppExit, replay::Player, render::OutputMode, suspend::Suspended};
 
 pub use crossterm::event::{KeyCode, KeyModifiers};
 
-pub(crate) fn build(app: &mut App) {
-    stdout().execute(EnableFocusChange).unwrap();
-    stdout().execute(EnableMouseCapture).unwrap();
-    stdout().execute(EnableBracketedPaste).unwrap();
+/// Without release events from the terminal a key counts as held until it
+/// has not been seen for this long, which covers the usual autorepeat delay.
+const HOLD_TIMEOUT: Duration = Duration::from_millis(600);
+
+/// Asked of terminals with the kitty keyboard protocol. Every key is sent as
+/// an escape code so text keys report releases too, and alternate keys make
+/// shifted keys arrive as what they type, `?` rather than shift and `/`.
+const KEYBOARD_FLAGS: KeyboardEnhancementFlags = KeyboardEnhancementFlags::DISAMBIGUATE_ESCAPE_CODES
+    .union(KeyboardEnhancementFlags::REPORT_EVENT_TYPES)
+    .union(KeyboardEnhancementFlags::REPORT_ALL_KEYS_AS_ESCAPE_CODES)
+    .union(KeyboardEnhancementFlags::REPORT_ALTERNATE_KEYS);
+
+pub(crate) fn build(app: &mut App, foxin: &Foxin) {
+    app.insert_resource(foxin.features);
+    let enhanced = match enable_terminal_features(&foxin.features, OutputMode::of(foxin)) {
+        Ok(enhanced) => enhanced,
+        Err(err) => {
+            crate::error::fail(&mut app.world, FoxinError::Terminal(err));
+            false
+        },
+    };
+
     app.init_resource::<RawInputs>();
     app.init_resource::<TerminalFocus>();
     app.init_resource::<MousePosition>();
+    app.insert_resource(KeysHeld {
+        reports_releases: enhanced,
+        ..Default::default()
+    });
     app.add_event::<KeyPress>();
+    app.add_event::<KeyRelease>();
     app.add_event::<Resize>();
-    app.add_systems(First, gather_input);
+    app.add_event::<MouseScroll>();
+    app.add_systems(First, gather_input
+        .pipe(crate::error::report)
+        .in_set(GatherInput)
+        .after(crate::time::clear_logic_timeout)
+    );
+}
+
+pub(crate) fn cleanup(app: &mut App) {
+    let enhanced = app.world
+        .get_resource::<KeysHeld>()
+        .map(|held| held.reports_releases)
+        .unwrap_or(false);
+    let mode = app.world.get_resource::<OutputMode>().copied();
+    if let (Some(features), Some(mode)) = (app.world.get_resource::<TerminalFeatures>(), mode) {
+        std::mem::drop(disable_terminal_features(features, enhanced, mode));
+    }
 }
 
-pub(crate) fn cleanup(_: &mut App) {
-    std::mem::drop(stdout().execute(DisableFocusChange));
-    std::mem::drop(stdout().execute(DisableMouseCapture));
-    std::mem::drop(stdout().execute(DisableBracketedPaste));
+/// What foxin asks the terminal to report, chosen on the [`Foxin`] plugin
+#[derive(Resource, Debug, Copy, Clone)]
+pub(crate) struct TerminalFeatures {
+    pub(crate) keyboard_enhancement: bool,
+    pub(crate) mouse: bool,
+    pub(crate) focus_events: bool,
+    pub(crate) bracketed_paste: bool,
 }
 
+impl Default for TerminalFeatures {
+    fn default() -> Self {
+        Self {
+            keyboard_enhancement: true,
+            mouse: true,
+            focus_events: true,
+            bracketed_paste: true,
+        }
+    }
+}
+
+/// Returns whether keyboard enhancement ended up enabled
+pub(crate) fn enable_terminal_features(features: &TerminalFeatures, mode: OutputMode) -> io::Result<bool> {
+    if mode.headless {
+        return Ok(false);
+    }
+    if features.focus_events {
+        stdout().execute(EnableFocusChange)?;
+    }
+    if features.mouse {
+        stdout().execute(EnableMouseCapture)?;
+    }
+    if features.bracketed_paste {
+        stdout().execute(EnableBracketedPaste)?;
+    }
+
+    let enhanced = features.keyboard_enhancement
+        && supports_keyboard_enhancement().unwrap_or(false);
+    if enhanced {
+        stdout().execute(PushKeyboardEnhancementFlags(KEYBOARD_FLAGS))?;
+    }
+    Ok(enhanced)
+}
+
+/// Turns off as much as it can even if a step fails, and returns the first error
+pub(crate) fn disable_terminal_features(
+    features: &TerminalFeatures,
+    enhanced: bool,
+    mode: OutputMode,
+) -> io::Result<()> {
+    if mode.headless {
+        return Ok(());
+    }
+    let mut out = stdout();
+    [
+        if enhanced { out.execute(PopKeyboardEnhancementFlags).map(drop) } else { Ok(()) },
+        if features.focus_events { out.execute(DisableFocusChange).map(drop) } else { Ok(()) },
+        if features.mouse { out.execute(DisableMouseCapture).map(drop) } else { Ok(()) },
+        if features.bracketed_paste { out.execute(DisableBracketedPaste).map(drop) } else { Ok(()) },
+    ].into_iter().collect()
+}
+
+#[derive(SystemSet, Hash, Copy, Clone, PartialEq, Eq, Debug)]
+pub struct GatherInput;
+
 #[derive(Default, Debug, Resource)]
 pub struct RawInputs {
-    raw: Vec<Event>,
+    pub(crate) raw: Vec<Event>,
+    /// What the terminal sent, which differs from `raw` while a recording
+    /// plays back in its place
+    pub(crate) terminal: Vec<Event>,
+    frames: u64,
 }
 
 impl RawInputs {
-    fn reset(&mut self) {
+    pub(crate) fn reset(&mut self) {
         self.raw.truncate(0);
+        self.terminal.truncate(0);
+        self.frames += 1;
+    }
+
+    /// Index of the frame these inputs were gathered on
+    pub fn frame(&self) -> u64 {
+        self.frames.saturating_sub(1)
     }
 
     pub fn events(&self) -> impl Iterator<Item = &Event> {
@@ -82,40 +199,199 @@ impl TerminalFocus {
 #[derive(Default, Debug, Resource)]
 pub struct MousePosition(pub U16Vec2);
 
+#[derive(Debug, Copy, Clone)]
+pub struct HeldKey {
+    pub modifiers: KeyModifiers,
+    pub since: Instant,
+    last_seen: Instant,
+}
+
+/// Keys that are down. This is exact when the terminal
+/// [reports releases](Self::reports_releases). Otherwise it is a best-effort
+/// guess: a key counts as held until it hasn't been pressed or repeated for a
+/// little while, so releases come late and quick taps of the same key can look
+/// like one long hold.
+#[derive(Default, Debug, Resource)]
+pub struct KeysHeld {
+    held: HashMap<KeyCode, HeldKey>,
+    reports_releases: bool,
+}
+
+impl KeysHeld {
+    /// Whether the terminal reports key releases. When it doesn't, releases
+    /// are synthesized once a key stops repeating.
+    pub fn reports_releases(&self) -> bool {
+        self.reports_releases
+    }
+
+    pub fn is_held(&self, code: KeyCode) -> bool {
+        self.held.contains_key(&code)
+    }
+
+    pub fn get(&self, code: KeyCode) -> Option<&HeldKey> {
+        self.held.get(&code)
+    }
+
+    pub fn held_for(&self, code: KeyCode, now: Instant) -> Option<Duration> {
+        self.held
+            .get(&code)
+            .map(|key| now.saturating_duration_since(key.since))
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (&KeyCode, &HeldKey)> {
+        self.held.iter()
+    }
+
+    /// Returns whether this press is a repeat. Without release events a
+    /// second tap can't be told from autorepeat by timing alone, so only the
+    /// terminal saying so counts.
+    fn press(&mut self, code: KeyCode, modifiers: KeyModifiers, kind: KeyEventKind, now: Instant) -> bool {
+        let was_held = match self.held.get_mut(&code) {
+            Some(key) => {
+                key.last_seen = now;
+                key.modifiers = modifiers;
+                true
+            },
+            None => {
+                self.held.insert(code, HeldKey {
+                    modifiers,
+                    since: now,
+                    last_seen: now,
+                });
+                false
+            },
+        };
+        kind == KeyEventKind::Repeat || (self.reports_releases && was_held)
+    }
+
+    /// A letter can be released under the other case if shift changed while
+    /// it was down
+    fn release(&mut self, code: KeyCode) -> Option<(KeyCode, HeldKey)> {
+        let other_case = match code {
+            KeyCode::Char(c) if c.is_lowercase() => c.to_uppercase().next().map(KeyCode::Char),
+            KeyCode::Char(c) => c.to_lowercase().next().map(KeyCode::Char),
+            _ => None,
+        };
+        [Some(code), other_case]
+            .into_iter()
+            .flatten()
+            .find_map(|code| Some((code, self.held.remove(&code)?)))
+    }
+
+    /// Let go of everything, for when releases can't be seen, like while the
+    /// terminal is unfocused or someone else has it
+    fn release_all(&mut self) -> Vec<(KeyCode, HeldKey)> {
+        self.held.drain().collect()
+    }
+
+    fn expire(&mut self, now: Instant) -> Vec<(KeyCode, HeldKey)> {
+        let expired = self.held
+            .iter()
+            .filter(|(_, key)| now.saturating_duration_since(key.last_seen) >= HOLD_TIMEOUT)
+            .map(|(code, key)| (*code, *key))
+            .collect::<Vec<_>>();
+        for (code, _) in expired.iter() {
+            self.held.remove(code);
+        }
+        expired
+    }
+
+    fn next_expiry(&self) -> Option<Instant> {
+        self.held
+            .values()
+            .map(|key| key.last_seen + HOLD_TIMEOUT)
+            .min()
+    }
+}
+
+/// Sent for the initial press of a key and for every autorepeat while it is
+/// held, with `repeat` set on the latter. Terminals that don't report
+/// releases may not mark autorepeats either, in which case every press
+/// looks like a new one.
 #[derive(bevy::ecs::event::Event, Debug)]
 pub struct KeyPress {
     pub code: KeyCode,
     pub modifiers: KeyModifiers,
+    pub repeat: bool,
+}
+
+#[derive(bevy::ecs::event::Event, Debug)]
+pub struct KeyRelease {
+    pub code: KeyCode,
+    pub modifiers: KeyModifiers,
+    pub held_for: Duration,
 }
 
 #[derive(bevy::ecs::event::Event, Debug, Copy, Clone)]
 pub struct Resize(pub U16Vec2);
 
-fn gather_input( 
+/// A notch of the mouse wheel at `position`. Positive `delta.y` scrolls down
+/// and positive `delta.x` scrolls right.
+#[derive(bevy::ecs::event::Event, Debug, Copy, Clone)]
+pub struct MouseScroll {
+    pub position: U16Vec2,
+    pub delta: I16Vec2,
+}
+
+#[allow(clippy::too_many_arguments)]
+pub(crate) fn gather_input(
     mut inputs: ResMut<RawInputs>,
     mut focus: ResMut<TerminalFocus>,
     mut mouse_pos: ResMut<MousePosition>,
+    mut held: ResMut<KeysHeld>,
+    mut logic_timeout: ResMut<LogicTimeout>,
     mut key_presses: EventWriter<KeyPress>,
+    mut key_releases: EventWriter<KeyRelease>,
     mut resize: EventWriter<Resize>,
-) {
+    mut scroll: EventWriter<MouseScroll>,
+    mut player: Option<ResMut<Player>>,
+    mut exit: EventWriter<AppExit>,
+    suspended: Res<Suspended>,
+    mode: Res<OutputMode>,
+    clock: Res<Clock>,
+) -> Result<(), FoxinError> {
+    let now = clock.now();
     inputs.reset();
     focus.reset();
-    while poll(Duration::from_secs(0)).unwrap() {
-        let event = read().unwrap();
+
+    // While suspended, input is for whoever has the terminal
+    let read_terminal = !mode.headless && !suspended.is_suspended();
+    while read_terminal && poll(Duration::from_secs(0)).map_err(FoxinError::Terminal)? {
+        inputs.terminal.push(read().map_err(FoxinError::Terminal)?);
+    }
+    // While a recording plays back it stands in for the terminal entirely
+    let events = match player.as_mut().filter(|player| player.is_playing()) {
+        Some(player) => {
+            if player.wants_quit(&inputs.terminal) {
+                exit.send_default();
+            }
+            player.take_pending().unwrap_or_default()
+        },
+        None => inputs.terminal.clone(),
+    };
+
+    for event in events {
         match event {
             Event::FocusGained => { focus.set(true); },
             Event::FocusLost => { focus.set(false); },
             Event::Mouse(event) => {
                 mouse_pos.0 = U16Vec2 { x: event.column, y: event.row };
-            },
-            Event::Key(event) => {
-                if event.kind != KeyEventKind::Release {
-                    key_presses.send(KeyPress {
-                        code: event.code,
-                        modifiers: event.modifiers
-                    });
+                let delta = match event.kind {
+                    MouseEventKind::ScrollUp => Some(I16Vec2::NEG_Y),
+                    MouseEventKind::ScrollDown => Some(I16Vec2::Y),
+                    MouseEventKind::ScrollLeft => Some(I16Vec2::NEG_X),
+                    MouseEventKind::ScrollRight => Some(I16Vec2::X),
+                    _ => None,
+                };
+                if let Some(delta) = delta {
+                    scroll.send(MouseScroll { position: mouse_pos.0, delta });
                 }
             },
+            Event::Key(event) => match key_input(event, &mut held, now) {
+                Some(KeyInput::Press(press)) => { key_presses.send(press); },
+                Some(KeyInput::Release(release)) => { key_releases.send(release); },
+                None => {},
+            },
             Event::Resize(x, y) => {
                 resize.send(Resize(U16Vec2 { x, y, }));
             },
@@ -123,4 +399,167 @@ fn gather_input(
         }
         inputs.raw.push(event);
     }
+
+    // Releases could be missed, so nothing stays held through them
+    if !focus.focused || suspended.is_suspended() {
+        for (code, key) in held.release_all() {
+            key_releases.send(KeyRelease {
+                code,
+                modifiers: key.modifiers,
+                held_for: now.saturating_duration_since(key.since),
+            });
+        }
+    }
+
+    if !held.reports_releases {
+        for (code, key) in held.expire(now) {
+            key_releases.send(KeyRelease {
+                code,
+                modifiers: key.modifiers,
+                held_for: key.last_seen.saturating_duration_since(key.since),
+            });
+        }
+        if let Some(when) = held.next_expiry() {
+            logic_timeout.by(when);
+        }
+    }
+
+    Ok(())
+}
+
+enum KeyInput {
+    Press(KeyPress),
+    Release(KeyRelease),
+}
+
+fn key_input(event: KeyEvent, held: &mut KeysHeld, now: Instant) -> Option<KeyInput> {
+    // Shift and the like on their own only show up in other keys' modifiers
+    if let KeyCode::Modifier(_) = event.code {
+        return None;
+    }
+    match event.kind {
+        KeyEventKind::Press | KeyEventKind::Repeat => {
+            let repeat = held.press(event.code, event.modifiers, event.kind, now);
+            Some(KeyInput::Press(KeyPress {
+                code: event.code,
+                modifiers: event.modifiers,
+                repeat,
+            }))
+        },
+        KeyEventKind::Release => {
+            let (code, key) = held.release(event.code)?;
+            Some(KeyInput::Release(KeyRelease {
+                code,
+                modifiers: key.modifiers,
+                held_for: now.saturating_duration_since(key.since),
+            }))
+        },
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bevy::ecs::event::Events;
+    use crossterm::event::ModifierKeyCode;
+
+    const A: KeyCode = KeyCode::Char('a');
+
+    fn press(held: &mut KeysHeld, kind: KeyEventKind, clock: &Clock) -> bool {
+        held.press(A, KeyModifiers::NONE, kind, clock.now())
+    }
+
+    #[test]
+    fn quick_taps_are_not_repeats_without_releases() {
+        let mut clock = Clock::manual();
+        let mut held = KeysHeld::default();
+        assert!(!press(&mut held, KeyEventKind::Press, &clock));
+        clock.advance(HOLD_TIMEOUT / 4);
+        assert!(!press(&mut held, KeyEventKind::Press, &clock));
+        assert!(held.is_held(A));
+        assert!(press(&mut held, KeyEventKind::Repeat, &clock));
+    }
+
+    #[test]
+    fn presses_while_held_are_repeats_with_releases() {
+        let mut clock = Clock::manual();
+        let mut held = KeysHeld { reports_releases: true, ..Default::default() };
+        assert!(!press(&mut held, KeyEventKind::Press, &clock));
+        clock.advance(HOLD_TIMEOUT / 4);
+        assert!(press(&mut held, KeyEventKind::Press, &clock));
+        assert!(held.release(A).is_some());
+        assert!(!press(&mut held, KeyEventKind::Press, &clock));
+    }
+
+    #[test]
+    fn held_keys_expire_once_they_stop_repeating() {
+        let mut clock = Clock::manual();
+        let start = clock.now();
+        let mut held = KeysHeld::default();
+        press(&mut held, KeyEventKind::Press, &clock);
+        clock.advance(HOLD_TIMEOUT / 2);
+        press(&mut held, KeyEventKind::Repeat, &clock);
+        assert_eq!(held.held_for(A, clock.now()), Some(HOLD_TIMEOUT / 2));
+        assert_eq!(held.next_expiry(), Some(start + HOLD_TIMEOUT / 2 + HOLD_TIMEOUT));
+
+        clock.advance(HOLD_TIMEOUT - Duration::from_millis(1));
+        assert!(held.expire(clock.now()).is_empty());
+        clock.advance(Duration::from_millis(1));
+        let expired = held.expire(clock.now());
+        assert_eq!(expired.len(), 1);
+        assert_eq!(expired[0].1.since, start);
+        assert!(!held.is_held(A));
+        assert_eq!(held.next_expiry(), None);
+    }
+
+    #[test]
+    fn kitty_keys_arrive_as_what_they_type() {
+        // With alternate keys, crossterm turns `CSI 47:63;2u` into `?` and
+        // `CSI 113:81;2u` into `Q`, both without shift. Without them these
+        // would be shift and `/` or `q`.
+        assert!(KEYBOARD_FLAGS.contains(KeyboardEnhancementFlags::REPORT_ALTERNATE_KEYS));
+        let clock = Clock::manual();
+        let mut held = KeysHeld { reports_releases: true, ..Default::default() };
+        let typed = KeyEvent::new(KeyCode::Char('?'), KeyModifiers::NONE);
+        let Some(KeyInput::Press(press)) = key_input(typed, &mut held, clock.now()) else { panic!() };
+        assert_eq!((press.code, press.modifiers), (KeyCode::Char('?'), KeyModifiers::NONE));
+
+        // `CSI 57441;2u`, shift on its own
+        let shift = KeyEvent::new(KeyCode::Modifier(ModifierKeyCode::LeftShift), KeyModifiers::SHIFT);
+        assert!(key_input(shift, &mut held, clock.now()).is_none());
+        let shift_up = KeyEvent::new_with_kind(shift.code, shift.modifiers, KeyEventKind::Release);
+        assert!(key_input(shift_up, &mut held, clock.now()).is_none());
+        assert_eq!(held.iter().count(), 1);
+    }
+
+    #[test]
+    fn letters_are_released_under_either_case() {
+        let mut clock = Clock::manual();
+        let mut held = KeysHeld { reports_releases: true, ..Default::default() };
+        held.press(KeyCode::Char('Q'), KeyModifiers::NONE, KeyEventKind::Press, clock.now());
+        clock.advance(HOLD_TIMEOUT);
+        // Shift let go of first
+        let release = KeyEvent::new_with_kind(KeyCode::Char('q'), KeyModifiers::NONE, KeyEventKind::Release);
+        let Some(KeyInput::Release(release)) = key_input(release, &mut held, clock.now()) else { panic!() };
+        assert_eq!((release.code, release.held_for), (KeyCode::Char('Q'), HOLD_TIMEOUT));
+        assert!(!held.is_held(KeyCode::Char('Q')));
+    }
+
+    #[test]
+    fn losing_focus_releases_held_keys() {
+        let mut app = crate::render::tests::headless_app(U16Vec2::new(1, 1));
+        app.update();
+        let now = app.world.resource::<Clock>().now();
+        let mut held = app.world.resource_mut::<KeysHeld>();
+        held.reports_releases = true;
+        held.press(A, KeyModifiers::NONE, KeyEventKind::Press, now);
+        app.update();
+        assert!(app.world.resource::<KeysHeld>().is_held(A));
+
+        app.world.resource_mut::<TerminalFocus>().focused = false;
+        app.update();
+        assert!(!app.world.resource::<KeysHeld>().is_held(A));
+        let released = app.world.resource_mut::<Events<KeyRelease>>().drain().map(|r| r.code).collect::<Vec<_>>();
+        assert_eq!(released, [A]);
+    }
 }
@@ -1,11 +1,19 @@
-use bevy::app::{App, Plugin};
+use bevy::{
+    app::{App, Plugin},
+    ecs::world::World,
+    math::U16Vec2,
+};
+use std::{
+    path::PathBuf,
+    time::{Duration, Instant},
+};
 
 macro_rules! add_modules(
     ($($module:ident)*) => {
         $(pub mod $module;)*
 
-        fn build(app: &mut App) {
-            $($module::build(app);)*
+        fn build(app: &mut App, foxin: &Foxin) {
+            $($module::build(app, foxin);)*
         }
 
         fn cleanup(app: &mut App) {
@@ -15,30 +23,260 @@ macro_rules! add_modules(
 );
 
 add_modules!(
+    error
     schedule
     quit
     render
+    min_size
+    blend
+    float
+    frame
+    scroll
+    visibility
+    cursor
+    animate
     input
     time
+    timer
+    diagnostics
+    replay
+    suspend
+    focus
 );
 
-pub struct Foxin;
+pub use suspend::{suspend, resume};
+
+/// The foxin plugin. Start from [`Foxin::new`] and turn on or off what the
+/// game needs.
+#[derive(Clone)]
+pub struct Foxin {
+    pub(crate) features: input::TerminalFeatures,
+    pub(crate) record: Option<PathBuf>,
+    pub(crate) playback: Option<replay::Playback>,
+    pub(crate) pause_on_focus_loss: Option<focus::FocusPolicy>,
+    pub(crate) min_terminal_size: U16Vec2,
+    pub(crate) screen: render::Screen,
+    pub(crate) backend: render::OutputBackend,
+    pub(crate) max_fps: f32,
+    pub(crate) diagnostics_overlay: Option<input::KeyCode>,
+    pub(crate) clock: time::Clock,
+    pub(crate) default_colors: blend::DefaultColors,
+}
+
+impl Default for Foxin {
+    fn default() -> Self {
+        Self {
+            features: input::TerminalFeatures::default(),
+            record: None,
+            playback: None,
+            pause_on_focus_loss: None,
+            min_terminal_size: U16Vec2::ZERO,
+            screen: render::Screen::Alternate,
+            backend: render::OutputBackend::Terminal,
+            max_fps: 10.0,
+            diagnostics_overlay: None,
+            clock: time::Clock::real(),
+            default_colors: blend::DefaultColors::default(),
+        }
+    }
+}
+
+impl Foxin {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Ask the terminal for key repeat and release events when it supports
+    /// the kitty keyboard protocol
+    pub fn keyboard_enhancement(mut self, enabled: bool) -> Self {
+        self.features.keyboard_enhancement = enabled;
+        self
+    }
+
+    /// Capture the mouse, which stops the terminal selecting text
+    pub fn mouse(mut self, enabled: bool) -> Self {
+        self.features.mouse = enabled;
+        self
+    }
+
+    pub fn focus_events(mut self, enabled: bool) -> Self {
+        self.features.focus_events = enabled;
+        self
+    }
+
+    pub fn bracketed_paste(mut self, enabled: bool) -> Self {
+        self.features.bracketed_paste = enabled;
+        self
+    }
+
+    /// Draw on the alternate screen, which the terminal swaps back out on
+    /// exit, or with `false` over the whole main screen, so the last frame
+    /// stays in the scrollback. The default is `true`.
+    pub fn alternate_screen(mut self, enabled: bool) -> Self {
+        self.screen = match enabled {
+            true => render::Screen::Alternate,
+            false => render::Screen::Main,
+        };
+        self
+    }
+
+    pub fn screen(mut self, screen: render::Screen) -> Self {
+        self.screen = screen;
+        self
+    }
+
+    /// Draw somewhere other than the terminal, e.g. nowhere in tests
+    pub fn backend(mut self, backend: render::OutputBackend) -> Self {
+        self.backend = backend;
+        self
+    }
+
+    /// The starting [`MaxRenderFrequency`](time::MaxRenderFrequency)
+    pub fn max_fps(mut self, fps: f32) -> Self {
+        self.max_fps = fps;
+        self
+    }
+
+    /// Where the game gets the time, e.g. a scaled clock to replay faster
+    pub fn clock(mut self, clock: time::Clock) -> Self {
+        self.clock = clock;
+        self
+    }
+
+    /// The terminal theme's colors, which [`Dim`](blend::Dim) darkens where
+    /// cells use the defaults
+    pub fn default_colors(mut self, colors: blend::DefaultColors) -> Self {
+        self.default_colors = colors;
+        self
+    }
+
+    /// Show frame timings in a corner while this key is toggled on, see
+    /// [`FrameDiagnostics`](diagnostics::FrameDiagnostics)
+    pub fn diagnostics_overlay(mut self, key: input::KeyCode) -> Self {
+        self.diagnostics_overlay = Some(key);
+        self
+    }
+
+    /// Smallest terminal the UI can be laid out in
+    pub fn min_terminal_size(mut self, size: U16Vec2) -> Self {
+        self.min_terminal_size = size;
+        self
+    }
+
+    /// Write every frame's raw input to this file
+    pub fn record(mut self, path: impl Into<PathBuf>) -> Self {
+        self.record = Some(path.into());
+        self
+    }
+
+    /// Feed input from a recording instead of the terminal
+    pub fn playback(mut self, playback: replay::Playback) -> Self {
+        self.playback = Some(playback);
+        self
+    }
+
+    /// Slow down and pause while the terminal doesn't have focus
+    pub fn pause_on_focus_loss(mut self, policy: focus::FocusPolicy) -> Self {
+        self.pause_on_focus_loss = Some(policy);
+        self
+    }
+}
 
 impl Plugin for Foxin {
     fn build(&self, app: &mut App) {
-        build(app);
+        build(app, self);
         app.set_runner(runner);
     }
 }
 
 fn runner(mut app: App) {
-    loop {
+    while !error::failed(&app.world) {
         app.update();
-        if quit::should_quit(&app) {
+        if quit::should_quit(&app) || error::failed(&app.world) {
             break;
         }
-        let max_sleep = app.world.run_system(*time::MAX_SLEEP_SYSTEM.get().unwrap()).unwrap();
-        crossterm::event::poll(max_sleep).unwrap();
+        let max_sleep = time::run_max_sleep(&mut app.world);
+        if let Err(err) = wait(&app.world, max_sleep) {
+            error::fail(&mut app.world, error::FoxinError::Terminal(err));
+        }
     }
     cleanup(&mut app);
 }
+
+/// How often the runner checks for job control signals while it waits, as
+/// they don't interrupt `poll`
+const SIGNAL_CHECK: Duration = Duration::from_millis(50);
+
+/// Wait up to `max_sleep` for input, or for a signal to handle
+fn wait(world: &World, max_sleep: Duration) -> std::io::Result<()> {
+    // Playback still reads the terminal, for its quit key
+    let read_terminal = !render::is_headless(world) && !suspend::is_suspended(world);
+    let check_signals = suspend::watching_signals(world);
+    let deadline = Instant::now() + max_sleep;
+    loop {
+        let left = deadline.saturating_duration_since(Instant::now());
+        let slice = if check_signals { left.min(SIGNAL_CHECK) } else { left };
+        if read_terminal {
+            if crossterm::event::poll(slice)? {
+                return Ok(());
+            }
+        } else {
+            std::thread::sleep(slice);
+        }
+        if slice == left || suspend::signal_pending(world) {
+            return Ok(());
+        }
+    }
+}
+
+/// Put the terminal back the way we found it without needing the app, for
+/// when we are panicking.
+fn restore_terminal(mode: render::OutputMode) {
+    // Turning off features that were never turned on is harmless
+    let features = input::TerminalFeatures::default();
+    std::mem::drop(input::disable_terminal_features(&features, true, mode));
+    std::mem::drop(render::leave_terminal(mode));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bevy::ecs::change_detection::DetectChanges;
+    use ratatui::widgets::{Block, Paragraph};
+    use render::tests::{headless_app, request_frame};
+
+    #[test]
+    fn headless_apps_draw_off_screen() {
+        let mut app = headless_app(U16Vec2::new(12, 3));
+        app.world.spawn((
+            render::Layer(0),
+            render::FoxinWidget::new(Paragraph::new("hello").block(Block::bordered())),
+        ));
+        app.update();
+
+        let buffer = render::headless_buffer(&app.world).unwrap();
+        assert_eq!(*buffer, ratatui::buffer::Buffer::with_lines(vec![
+            "┌──────────┐",
+            "│hello     │",
+            "└──────────┘",
+        ]));
+        assert!(!error::failed(&app.world));
+    }
+
+    #[test]
+    fn widgets_are_only_drawn_again_when_changed() {
+        let mut app = headless_app(U16Vec2::new(5, 1));
+        let widget = app.world.spawn((render::Layer(0), render::FoxinWidget::new(Paragraph::new("one")))).id();
+        app.update();
+        let drawn = app.world.entity(widget).get_ref::<render::DrawBuffer>().unwrap().last_changed();
+
+        request_frame(&mut app);
+        let buffer = app.world.entity(widget).get_ref::<render::DrawBuffer>().unwrap();
+        assert_eq!(buffer.last_changed(), drawn);
+
+        *app.world.get_mut::<render::FoxinWidget>(widget).unwrap() = render::FoxinWidget::new(Paragraph::new("two"));
+        request_frame(&mut app);
+        let buffer = render::headless_buffer(&app.world).unwrap();
+        assert_eq!(*buffer, ratatui::buffer::Buffer::with_lines(vec!["two  "]));
+    }
+}
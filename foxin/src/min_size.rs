@@ -0,0 +1,93 @@
+use bevy::{
+    app::App,
+    ecs::{
+        change_detection::DetectChanges,
+        system::{Res, ResMut, Resource},
+    },
+    math::U16Vec2,
+};
+use ratatui::{
+    buffer::Buffer,
+    layout::{Alignment, Rect},
+    text::Text,
+    widgets::{Paragraph, Widget, Wrap},
+};
+use crate::time::{Clock, RenderTimeout};
+
+pub(crate) fn build(app: &mut App, foxin: &crate::Foxin) {
+    app.insert_resource(MinTerminalSize(foxin.min_terminal_size));
+    app.add_systems(crate::schedule::PostLogic, redraw_on_change);
+}
+
+pub(crate) fn cleanup(_: &mut App) {}
+
+/// Below this size nothing is laid out, and the screen asks for a bigger
+/// terminal instead
+#[derive(Resource, Debug, Default, Copy, Clone, PartialEq, Eq)]
+pub struct MinTerminalSize(pub U16Vec2);
+
+impl MinTerminalSize {
+    pub fn too_small(&self, area: Rect) -> bool {
+        area.width < self.0.x || area.height < self.0.y
+    }
+
+    pub(crate) fn render_message(&self, area: Rect, buffer: &mut Buffer) {
+        buffer.reset();
+        let text = Text::from(format!(
+            "Please enlarge the terminal\n(need {}x{}, have {}x{})",
+            self.0.x, self.0.y, area.width, area.height,
+        ));
+        let height = (text.height() as u16).min(area.height);
+        let area = Rect {
+            y: area.y + (area.height - height) / 2,
+            height,
+            ..area
+        };
+        Paragraph::new(text)
+            .alignment(Alignment::Center)
+            .wrap(Wrap { trim: true })
+            .render(area, buffer);
+    }
+}
+
+fn redraw_on_change(
+    min_size: Res<MinTerminalSize>,
+    mut timeout: ResMut<RenderTimeout>,
+    clock: Res<Clock>,
+) {
+    if min_size.is_changed() && !min_size.is_added() {
+        timeout.by(clock.now());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ratatui::widgets::Paragraph;
+    use crate::render::{self, tests::{headless_app_with, resize_headless}, FoxinWidget, Layer};
+
+    fn lines(app: &App) -> Vec<String> {
+        let buffer = render::headless_buffer(&app.world).unwrap();
+        (0..buffer.area.height)
+            .map(|y| (0..buffer.area.width).map(|x| buffer.get(x, y).symbol()).collect::<String>())
+            .map(|line| line.trim().to_string())
+            .collect()
+    }
+
+    #[test]
+    fn small_terminals_are_asked_to_grow() {
+        let foxin = crate::Foxin::new().min_terminal_size(U16Vec2::new(30, 2));
+        let mut app = headless_app_with(foxin, U16Vec2::new(30, 2));
+        app.world.spawn((Layer(0), FoxinWidget::new(Paragraph::new("game"))));
+        app.update();
+        assert_eq!(lines(&app), ["game", ""]);
+
+        resize_headless(&mut app, U16Vec2::new(29, 2));
+        app.update();
+        assert_eq!(lines(&app), ["Please enlarge the terminal", "(need 30x2, have 29x2)"]);
+
+        resize_headless(&mut app, U16Vec2::new(30, 3));
+        app.update();
+        assert_eq!(lines(&app), ["game", "", ""]);
+    }
+}
@@ -8,7 +8,7 @@ use bevy::{
 
 pub use bevy::app::AppExit;
 
-pub(crate) fn build(app: &mut App) {
+pub(crate) fn build(app: &mut App, _: &crate::Foxin) {
     app.init_resource::<ShouldQuit>();
     app.add_systems(Last, read_quit_events);
 }
@@ -2,42 +2,371 @@ use bevy::{
     app::{App, Startup, Update},
     ecs::{
         event::EventReader,
-        system::{Resource, ResMut, Query, Res},
-        component::Component,
+        change_detection::{DetectChanges, DetectChangesMut, Ref},
+        query::{Changed, With, Without},
+        removal_detection::RemovedComponents,
+        schedule::{IntoSystemConfigs, SystemSet},
+        system::{Commands, IntoSystem, Local, Resource, ResMut, Query, Res, SystemChangeTick},
+        component::{Component, Tick},
         entity::Entity,
+        world::World,
     },
     hierarchy::{Children, HierarchyPlugin},
+    math::{IVec2, U16Vec2},
 };
 use crossterm::{
+    cursor::{SetCursorStyle, Show},
+    style::Print,
     terminal::{disable_raw_mode, enable_raw_mode, EnterAlternateScreen, LeaveAlternateScreen},
-    ExecutableCommand,
+    ExecutableCommand, QueueableCommand,
+};
+use ratatui::{
+    backend::{Backend as _, ClearType, CrosstermBackend, TestBackend, WindowSize},
+    buffer::{Buffer, Cell},
+    layout::Rect,
+    widgets::WidgetRef,
+    TerminalOptions,
 };
-use ratatui::{backend::{Backend, CrosstermBackend}, layout::Rect, buffer::Buffer};
 use std::{
     collections::VecDeque,
-    io::{stdout, Stdout},
-    time::Instant,
+    io::{self, stdout, Stdout, Write},
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    },
+};
+use crate::{
+    blend::{self, Blend, DefaultColors, Dim, Opacity},
+    float::{Floating, SizeLimits, ZIndex},
+    min_size::MinTerminalSize,
+    frame::{Frame, FrameBuffer},
+    scroll::{Scroll, Viewport},
+    visibility::{self, Visibility},
+    error::{FoxinError, report},
+    time::Clock,
 };
 
-pub(crate) fn build(app: &mut App) {
-    enable_raw_mode().unwrap();
-    stdout().execute(EnterAlternateScreen).unwrap();
-    app.insert_resource(Terminal(ratatui::Terminal::new(CrosstermBackend::new(stdout())).unwrap()));
+pub(crate) fn build(app: &mut App, foxin: &crate::Foxin) {
+    let mode = OutputMode::of(foxin);
+    app.insert_resource(mode);
+    let written = BytesWritten::default();
+    app.insert_resource(written.clone());
+    let viewport = match foxin.screen {
+        Screen::Alternate | Screen::Main => ratatui::Viewport::Fullscreen,
+        Screen::Inline(height) => ratatui::Viewport::Inline(height),
+    };
+    let backend = match foxin.backend {
+        OutputBackend::Terminal => FoxinBackend::Terminal(CrosstermBackend::new(CountingStdout {
+            out: stdout(),
+            written: written.0,
+        })),
+        OutputBackend::Headless(size) => FoxinBackend::Headless(TestBackend::new(size.x, size.y)),
+    };
+    let terminal = enter_terminal(mode)
+        .and_then(|_| ratatui::Terminal::with_options(backend, TerminalOptions { viewport }));
+    match terminal {
+        Ok(terminal) => { app.insert_resource(Terminal(terminal)); },
+        Err(err) => crate::error::fail(&mut app.world, FoxinError::Terminal(err)),
+    }
     app.add_plugins(HierarchyPlugin);
-    app.add_systems(crate::schedule::PreLayout, terminal_resize);
-    app.add_systems(crate::schedule::Layout, do_layout);
-    app.add_systems(crate::schedule::PostRender, do_render);
+    app.init_resource::<LastRender>();
+    app.init_resource::<ForceRedraw>();
+    app.redraw_on_change::<DrawBuffer>()
+        .redraw_on_change::<DrawArea>()
+        .redraw_on_change::<Layout>()
+        .redraw_on_change::<Constraint>()
+        .redraw_on_change::<Layer>()
+        .redraw_on_change::<Children>()
+        .redraw_on_change::<FoxinWidget>();
+    app.add_systems(crate::schedule::PreLayout, terminal_resize.pipe(report));
+    app.add_systems(crate::schedule::Layout, do_layout.pipe(report));
+    app.add_systems(crate::schedule::PreLayout, (add_widget_buffers, add_layer_caches));
+    app.add_systems(crate::schedule::Render, render_widgets.in_set(RenderWidgets));
+    app.add_systems(crate::schedule::PostRender, do_render.pipe(report).in_set(DrawFrame));
     app.add_systems(Update, redraw_on_resize); 
-    app.add_systems(Startup, initial_clear);
+    app.add_systems(Startup, initial_clear.pipe(report));
+}
+
+pub(crate) fn cleanup(app: &mut App) {
+    let Some(mode) = app.world.get_resource::<OutputMode>().copied() else { return; };
+    // Leave the shell prompt below what was drawn
+    if !mode.alternate_screen && !mode.headless {
+        if let Some(mut terminal) = app.world.get_resource_mut::<Terminal>() {
+            let area = terminal.0.get_frame().size();
+            std::mem::drop(terminal.0.set_cursor(0, area.bottom().saturating_sub(1)));
+            std::mem::drop(terminal.0.backend_mut().queue(Print("\r\n")));
+        }
+    }
+    std::mem::drop(leave_terminal(mode));
+}
+
+/// Where foxin draws
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq)]
+pub enum Screen {
+    /// The whole terminal, switching back to what was there on exit
+    #[default]
+    Alternate,
+    /// The whole terminal, leaving the last frame in the scrollback on exit
+    Main,
+    /// This many lines below the cursor, left in the scrollback on exit
+    Inline(u16),
+}
+
+/// What foxin draws to
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq)]
+pub enum OutputBackend {
+    /// The terminal on stdout
+    #[default]
+    Terminal,
+    /// An off-screen buffer of this size. Nothing is written to or read from
+    /// the terminal, so apps can run in tests.
+    Headless(U16Vec2),
+}
+
+/// How an app uses the terminal, kept apart from [`Terminal`] so the
+/// terminal can be restored from places that can't see the app
+#[derive(Resource, Debug, Copy, Clone, PartialEq, Eq)]
+pub(crate) struct OutputMode {
+    /// The terminal is left alone, see [`OutputBackend::Headless`]
+    pub(crate) headless: bool,
+    pub(crate) alternate_screen: bool,
+}
+
+impl OutputMode {
+    pub(crate) fn of(foxin: &crate::Foxin) -> Self {
+        Self {
+            headless: matches!(foxin.backend, OutputBackend::Headless(_)),
+            alternate_screen: foxin.screen == Screen::Alternate,
+        }
+    }
+}
+
+pub(crate) fn is_headless(world: &World) -> bool {
+    world
+        .get_resource::<OutputMode>()
+        .is_some_and(|mode| mode.headless)
+}
+
+/// What an [`OutputBackend::Headless`] app last drew
+pub fn headless_buffer(world: &World) -> Option<&Buffer> {
+    match world.get_resource::<Terminal>()?.0.backend() {
+        FoxinBackend::Headless(backend) => Some(backend.buffer()),
+        _ => None,
+    }
+}
+
+/// Bytes written to the terminal since the last call
+pub(crate) fn take_bytes_written(world: &World) -> usize {
+    world
+        .get_resource::<BytesWritten>()
+        .map(|written| written.0.swap(0, Ordering::Relaxed))
+        .unwrap_or(0)
+}
+
+/// Counted by the app's [`CountingStdout`], which ratatui doesn't let us
+/// reach once it owns it
+#[derive(Resource, Default, Clone)]
+struct BytesWritten(Arc<AtomicUsize>);
+
+/// Stdout that counts what goes through it, for
+/// [`FrameDiagnostics`](crate::diagnostics::FrameDiagnostics)
+pub(crate) struct CountingStdout {
+    out: Stdout,
+    written: Arc<AtomicUsize>,
+}
+
+impl Write for CountingStdout {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let written = self.out.write(buf)?;
+        self.written.fetch_add(written, Ordering::Relaxed);
+        Ok(written)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.out.flush()
+    }
+}
+
+pub(crate) enum FoxinBackend {
+    Terminal(CrosstermBackend<CountingStdout>),
+    Headless(TestBackend),
+    /// Fails every call, as a terminal that went away would
+    #[cfg(test)]
+    Failing,
+}
+
+impl FoxinBackend {
+    /// Queue a terminal command that ratatui has no method for
+    pub(crate) fn queue(&mut self, command: impl crossterm::Command) -> io::Result<()> {
+        match self {
+            Self::Terminal(backend) => backend.queue(command).map(|_| ()),
+            Self::Headless(_) => Ok(()),
+            #[cfg(test)]
+            Self::Failing => Err(io::ErrorKind::BrokenPipe.into()),
+        }
+    }
+}
+
+macro_rules! delegate {
+    ($self:ident.$method:ident($($arg:expr),*)) => {
+        match $self {
+            FoxinBackend::Terminal(backend) => backend.$method($($arg),*),
+            FoxinBackend::Headless(backend) => backend.$method($($arg),*),
+            #[cfg(test)]
+            FoxinBackend::Failing => Err(io::ErrorKind::BrokenPipe.into()),
+        }
+    };
+}
+
+impl ratatui::backend::Backend for FoxinBackend {
+    fn draw<'a, I>(&mut self, content: I) -> io::Result<()>
+    where
+        I: Iterator<Item = (u16, u16, &'a Cell)>,
+    {
+        delegate!(self.draw(content))
+    }
+
+    fn append_lines(&mut self, n: u16) -> io::Result<()> {
+        delegate!(self.append_lines(n))
+    }
+
+    fn hide_cursor(&mut self) -> io::Result<()> {
+        delegate!(self.hide_cursor())
+    }
+
+    fn show_cursor(&mut self) -> io::Result<()> {
+        delegate!(self.show_cursor())
+    }
+
+    fn get_cursor(&mut self) -> io::Result<(u16, u16)> {
+        delegate!(self.get_cursor())
+    }
+
+    fn set_cursor(&mut self, x: u16, y: u16) -> io::Result<()> {
+        delegate!(self.set_cursor(x, y))
+    }
+
+    fn clear(&mut self) -> io::Result<()> {
+        delegate!(self.clear())
+    }
+
+    fn clear_region(&mut self, clear_type: ClearType) -> io::Result<()> {
+        delegate!(self.clear_region(clear_type))
+    }
+
+    fn size(&self) -> io::Result<Rect> {
+        delegate!(self.size())
+    }
+
+    fn window_size(&mut self) -> io::Result<WindowSize> {
+        delegate!(self.window_size())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        match self {
+            Self::Terminal(backend) => ratatui::backend::Backend::flush(backend),
+            Self::Headless(backend) => backend.flush(),
+            #[cfg(test)]
+            Self::Failing => Err(io::ErrorKind::BrokenPipe.into()),
+        }
+    }
+}
+
+pub(crate) fn enter_terminal(mode: OutputMode) -> io::Result<()> {
+    if mode.headless {
+        return Ok(());
+    }
+    enable_raw_mode()?;
+    if mode.alternate_screen {
+        stdout().execute(EnterAlternateScreen)?;
+    }
+    Ok(())
+}
+
+/// Undoes as much as it can even if a step fails, and returns the first error
+pub(crate) fn leave_terminal(mode: OutputMode) -> io::Result<()> {
+    if mode.headless {
+        return Ok(());
+    }
+    [
+        disable_raw_mode(),
+        if mode.alternate_screen { stdout().execute(LeaveAlternateScreen).map(drop) } else { Ok(()) },
+        stdout().execute(SetCursorStyle::DefaultUserShape).map(drop),
+        stdout().execute(Show).map(drop),
+    ].into_iter().collect()
+}
+
+/// Throw away what we think is on screen and draw everything again
+pub(crate) fn full_redraw(world: &mut World) -> io::Result<()> {
+    if let Some(mut terminal) = world.get_resource_mut::<Terminal>() {
+        terminal.0.clear()?;
+    }
+    world.resource_mut::<ForceRedraw>().0 = true;
+    let now = world.resource::<Clock>().now();
+    if let Some(mut timeout) = world.get_resource_mut::<crate::time::RenderTimeout>() {
+        timeout.by(now);
+    }
+    Ok(())
+}
+
+/// Whether the terminal is below [`MinTerminalSize`], in which case only the
+/// message asking for more room is drawn
+pub(crate) fn too_small(world: &mut World) -> bool {
+    let Some(min_size) = world.get_resource::<MinTerminalSize>().copied() else { return false; };
+    world
+        .get_resource_mut::<Terminal>()
+        .is_some_and(|mut terminal| min_size.too_small(terminal.0.get_frame().size()))
+}
+
+/// Called once the render schedules have run, so that changes they made
+/// don't count as reasons to render again
+pub(crate) fn rendered(world: &mut World) {
+    let tick = world.read_change_tick();
+    world.resource_mut::<LastRender>().0 = tick;
 }
 
-pub(crate) fn cleanup(_: &mut App) {
-    std::mem::drop(disable_raw_mode());
-    std::mem::drop(stdout().execute(LeaveAlternateScreen));
+pub trait RenderAppExt {
+    /// Request a render whenever a `C` is added, changed or removed outside
+    /// of the render schedules
+    fn redraw_on_change<C: Component>(&mut self) -> &mut Self;
+
+    /// Like `redraw_on_change`, but also lay out and draw every layer again,
+    /// for components that change layout in ways that can't be tracked
+    fn relayout_on_change<C: Component>(&mut self) -> &mut Self;
+}
+
+impl RenderAppExt for App {
+    fn redraw_on_change<C: Component>(&mut self) -> &mut Self {
+        self.add_systems(crate::schedule::PostLogic, request_render_on_change::<C, false>)
+    }
+
+    fn relayout_on_change<C: Component>(&mut self) -> &mut Self {
+        self.add_systems(crate::schedule::PostLogic, request_render_on_change::<C, true>)
+    }
+}
+
+#[derive(Resource)]
+struct LastRender(Tick);
+
+impl Default for LastRender {
+    fn default() -> Self {
+        Self(Tick::new(0))
+    }
 }
 
+/// Set when the screen was cleared or something was removed, so everything
+/// must be laid out and drawn again
+#[derive(Resource, Default)]
+pub(crate) struct ForceRedraw(pub(crate) bool);
+
+/// A layer's draw buffers blended together, kept until one of them changes.
+/// Anything writing to a buffer every frame keeps its whole layer from being
+/// cached, so skip the write when there's nothing new to draw.
+#[derive(Component, Default)]
+struct LayerCache(Buffer);
+
 #[derive(Resource)]
-pub(crate) struct Terminal(pub(crate) ratatui::Terminal<CrosstermBackend<Stdout>>);
+pub(crate) struct Terminal(pub(crate) ratatui::Terminal<FoxinBackend>);
 
 #[derive(Component, PartialOrd, Ord, PartialEq, Eq, Default)]
 pub struct Layer(pub usize);
@@ -48,50 +377,169 @@ pub struct Layout(pub ratatui::layout::Layout);
 #[derive(Component, Default, Copy, Clone)]
 pub struct Constraint(pub ratatui::layout::Constraint);
 
-#[derive(Component, Default, Copy, Clone)]
+#[derive(Component, Default, Copy, Clone, PartialEq, Eq)]
 pub struct DrawArea(pub Rect);
 
 #[derive(Component, Default)]
 pub struct DrawBuffer(pub Buffer);
 
+impl DrawBuffer {
+    /// Empty every cell so that whatever is beneath shows through
+    pub fn clear(&mut self) {
+        let cell = crate::blend::transparent_cell();
+        for c in self.0.content.iter_mut() {
+            c.clone_from(&cell);
+        }
+    }
+}
+
+/// A ratatui widget drawn into the entity's [`DrawArea`] whenever it or the
+/// area changes. Entities with one are given a [`DrawBuffer`] if they don't
+/// already have one.
+#[derive(Component)]
+pub struct FoxinWidget(pub Box<dyn WidgetRef + Send + Sync>);
+
+impl FoxinWidget {
+    pub fn new<W: WidgetRef + Send + Sync + 'static>(widget: W) -> Self {
+        Self(Box::new(widget))
+    }
+}
+
+#[derive(SystemSet, Hash, Copy, Clone, PartialEq, Eq, Debug)]
+pub struct RenderWidgets;
+
+/// Composites layers and writes the frame to the terminal
+#[derive(SystemSet, Hash, Copy, Clone, PartialEq, Eq, Debug)]
+pub struct DrawFrame;
+
 fn redraw_on_resize(
-    mut reader: EventReader<crate::input::Resize>,
+    reader: EventReader<crate::input::Resize>,
     mut timeout: ResMut<crate::time::RenderTimeout>,
+    clock: Res<Clock>,
 ) {
     if !reader.is_empty() {
-        timeout.by(Instant::now());
+        timeout.by(clock.now());
+    }
+}
+
+fn request_render_on_change<C: Component, const RELAYOUT: bool>(
+    changed: Query<Ref<C>, Changed<C>>,
+    mut removed: RemovedComponents<C>,
+    last_render: Res<LastRender>,
+    ticks: SystemChangeTick,
+    mut force: ResMut<ForceRedraw>,
+    mut timeout: ResMut<crate::time::RenderTimeout>,
+    clock: Res<Clock>,
+) {
+    // Removals aren't remembered until the next render, so there's no way
+    // to tell which tree they came from
+    let removed = removed.read().count() > 0;
+    let changed = changed
+        .iter()
+        .any(|c| c.last_changed().is_newer_than(last_render.0, ticks.this_run()));
+    if removed || (RELAYOUT && changed) {
+        force.0 = true;
+    }
+    if removed || changed {
+        timeout.by(clock.now());
+    }
+}
+
+fn add_layer_caches(
+    mut commands: Commands,
+    layers: Query<Entity, (With<Layer>, Without<LayerCache>)>,
+) {
+    for entity in layers.iter() {
+        commands.entity(entity).insert(LayerCache::default());
+    }
+}
+
+fn add_widget_buffers(
+    mut commands: Commands,
+    widgets: Query<Entity, (With<FoxinWidget>, Without<DrawBuffer>)>,
+) {
+    for entity in widgets.iter() {
+        commands.entity(entity).insert(DrawBuffer::default());
+    }
+}
+
+fn render_widgets(
+    mut widgets: Query<(Ref<FoxinWidget>, &mut DrawBuffer)>,
+) {
+    for (widget, mut buffer) in widgets.iter_mut() {
+        // Layout marks the buffer changed when it resizes it, and anything
+        // else drawing over the widget does too
+        if !widget.is_changed() && !buffer.is_changed() {
+            continue;
+        }
+        buffer.clear();
+        let area = buffer.0.area;
+        widget.0.render_ref(area, &mut buffer.0);
     }
 }
 
 fn terminal_resize(
     mut terminal: ResMut<Terminal>,
-) {
-    terminal.0.autoresize().unwrap();
+    mut force: ResMut<ForceRedraw>,
+) -> Result<(), FoxinError> {
+    let before = terminal.0.get_frame().size();
+    terminal.0.autoresize().map_err(FoxinError::Terminal)?;
+    // Resizing clears the screen
+    if terminal.0.get_frame().size() != before {
+        force.0 = true;
+    }
+    Ok(())
 }
 
 fn initial_clear(
     mut terminal: ResMut<Terminal>,
-) {
-    terminal.0.clear().unwrap();
+    mut force: ResMut<ForceRedraw>,
+) -> Result<(), FoxinError> {
+    force.0 = true;
+    terminal.0.clear().map_err(FoxinError::Terminal)
 }
 
+#[allow(clippy::too_many_arguments)]
 fn do_layout(
-    terminal: Res<Terminal>,
+    mut terminal: ResMut<Terminal>,
+    force: Res<ForceRedraw>,
+    mut last_area: Local<Rect>,
     mut draw_areas: Query<&mut DrawArea>,
     mut draw_buffers: Query<&mut DrawBuffer>,
-    constraints: Query<&Constraint>,
-    layouts: Query<&Layout>,
-    children: Query<&Children>,
-    layers: Query<(&Layer, Entity)>,
-) {
+    constraints: Query<Ref<Constraint>>,
+    layouts: Query<Ref<Layout>>,
+    children: Query<Ref<Children>>,
+    layers: Query<(Ref<Layer>, Entity)>,
+    placements: Query<(Option<&Floating>, Option<&SizeLimits>, Option<&Visibility>)>,
+    mut frames: Query<(&Frame, Option<&mut FrameBuffer>)>,
+    mut scrolls: Query<(Ref<Scroll>, Option<&mut Viewport>)>,
+) -> Result<(), FoxinError> {
     let mut layers = layers
         .iter()
         .collect::<Vec<_>>();
-    layers.sort();
+    layers.sort_by(|(a, _), (b, _)| a.cmp(b));
     
-    let area = terminal.0.size().unwrap();
+    let area = terminal.0.get_frame().size();
+    let resized = area != *last_area;
+    *last_area = area;
+
+    for (layer, entity) in layers {
+        if placements.get(entity).is_ok_and(|(_, _, v)| visibility::is_hidden(v)) {
+            continue;
+        }
+        let dirty = resized
+            || force.0
+            || layer.is_changed()
+            || any_in_tree(entity, &children, |entity| {
+                constraints.get(entity).is_ok_and(|c| c.is_changed())
+                    || layouts.get(entity).is_ok_and(|l| l.is_changed())
+                    || children.get(entity).is_ok_and(|c| c.is_changed())
+                    || scrolls.get(entity).is_ok_and(|(s, _)| s.is_changed())
+            });
+        if !dirty {
+            continue;
+        }
 
-    for (_, entity) in layers {
         layout_layer(
             area,
             &mut draw_areas,
@@ -99,112 +547,408 @@ fn do_layout(
             &constraints,
             &layouts,
             &children,
+            &placements,
+            &mut frames,
+            &mut scrolls,
             entity
         );
     }
+
+    Ok(())
+}
+
+/// Breadth first search of `root` and its descendants
+fn any_in_tree(
+    root: Entity,
+    children: &Query<Ref<Children>>,
+    mut f: impl FnMut(Entity) -> bool,
+) -> bool {
+    let mut to_visit = VecDeque::new();
+    to_visit.push_back(root);
+
+    while let Some(entity) = to_visit.pop_front() {
+        if f(entity) {
+            return true;
+        }
+
+        to_visit.extend(children
+            .get(entity)
+            .ok()
+            .iter()
+            .flat_map(|v| v.iter())
+        );
+    }
+
+    false
 }
 
+#[allow(clippy::too_many_arguments)]
 fn layout_layer(
     area: Rect,
     draw_areas: &mut Query<&mut DrawArea>, 
     draw_buffers: &mut Query<&mut DrawBuffer>,
-    constraints: &Query<&Constraint>,
-    layouts: &Query<&Layout>,
-    children: &Query<&Children>,
+    constraints: &Query<Ref<Constraint>>,
+    layouts: &Query<Ref<Layout>>,
+    children: &Query<Ref<Children>>,
+    placements: &Query<(Option<&Floating>, Option<&SizeLimits>, Option<&Visibility>)>,
+    frames: &mut Query<(&Frame, Option<&mut FrameBuffer>)>,
+    scrolls: &mut Query<(Ref<Scroll>, Option<&mut Viewport>)>,
     entity: Entity,
 ) {
     struct LayoutContext {
         entity: Entity,
         area: Rect,
+        /// What the entity's area may grow into: the parent's area for
+        /// floating entities, or their own slot for tiled ones
+        container: Rect,
+        /// Where on screen the content shows, and how far scrolls around it
+        /// move it, as when rendering
+        clip: Rect,
+        shift: IVec2,
     }
     let mut to_layout = VecDeque::new();
     to_layout.push_back(LayoutContext {
         entity,
         area,
+        container: area,
+        clip: area,
+        shift: IVec2::ZERO,
     });
 
     while let Some(ctx) = to_layout.pop_front() {
-        if let Ok(mut area) = draw_areas.get_mut(ctx.entity) {
-            area.0 = ctx.area;
+        let (floating, limits, _) = placements.get(ctx.entity).unwrap_or_default();
+        let area = match (floating, limits) {
+            (Some(floating), limits) => {
+                let mut floating = *floating;
+                if let Some(limits) = limits {
+                    floating.size = floating.size
+                        .clamp(limits.min, limits.max.max(limits.min));
+                }
+                floating.place(ctx.container)
+            },
+            (None, Some(limits)) => limits.apply(ctx.area, ctx.container),
+            (None, None) => ctx.area,
+        };
+
+        let area = match frames.get_mut(ctx.entity) {
+            Ok((frame, buffer)) => {
+                if let Some(mut buffer) = buffer.filter(|b| b.0.area != area) {
+                    buffer.0.resize(area);
+                }
+                frame.0.inner(area)
+            },
+            Err(_) => area,
+        };
+
+        // Children of a scroll are laid out in its content rather than the
+        // part of it that shows
+        let (mut clip, mut shift) = (ctx.clip, ctx.shift);
+        let (area, inner) = match scrolls.get_mut(ctx.entity) {
+            Ok((scroll, viewport)) => {
+                let (area, scrollbar) = scroll.split(area);
+                clip = clip.intersection(shifted(area, shift));
+                shift += scroll.clamped_offset(area).as_ivec2();
+                if let Some(mut viewport) = viewport {
+                    viewport.set_if_neq(Viewport { area, scrollbar, on_screen: clip });
+                }
+                (area, scroll.content_area(area))
+            },
+            Err(_) => (area, area),
+        };
+
+        // Only write when something moved so change detection stays accurate
+        if let Ok(mut draw_area) = draw_areas.get_mut(ctx.entity) {
+            draw_area.set_if_neq(DrawArea(area));
         }
         
         if let Ok(mut buffer) = draw_buffers.get_mut(ctx.entity) {
-            buffer.0.resize(ctx.area);
+            if buffer.0.area != area {
+                buffer.0.resize(area);
+                buffer.clear();
+            }
         }
 
-        let children = children
+        let (floating, tiled): (Vec<_>, Vec<_>) = children
             .get(ctx.entity)
-            .ok()
-            .iter()
-            .map(|v| v.iter())
-            .flatten()
-            .map(|entity| (entity, constraints.get(*entity).cloned().unwrap_or_default()))
-            .collect::<Vec<_>>();
+            .map(|v| v.to_vec())
+            .unwrap_or_default()
+            .into_iter()
+            .filter(|entity| !placements.get(*entity).is_ok_and(|(_, _, v)| visibility::is_hidden(v)))
+            .partition(|entity| matches!(placements.get(*entity), Ok((Some(_), _, _))));
 
         let rects = layouts
             .get(ctx.entity)
             .map(|layout| layout.0.clone())
             .unwrap_or_default()
-            .constraints(children.iter().map(|(_, constraint)| constraint.0))
-            .split(ctx.area);
+            .constraints(tiled
+                .iter()
+                .map(|entity| constraints.get(*entity).map(|c| c.0).unwrap_or_default())
+            )
+            .split(inner);
 
-        for i in 0..children.len() {
+        for (i, child) in tiled.into_iter().enumerate() {
             to_layout.push_back(LayoutContext {
                 area: rects[i],
-                entity: *children[i].0,
+                entity: child,
+                // Growing any further would cover its siblings
+                container: rects[i],
+                clip,
+                shift,
+            });
+        }
+        for child in floating {
+            to_layout.push_back(LayoutContext {
+                area: inner,
+                entity: child,
+                container: inner,
+                clip,
+                shift,
             });
         }
     }
 }
 
+#[allow(clippy::type_complexity, clippy::too_many_arguments)]
 fn do_render(
     mut terminal: ResMut<Terminal>,
-    draw_buffers: Query<&DrawBuffer>,
-    children: Query<&Children>,
-    layers: Query<(&Layer, Entity)>,
-) {
+    min_size: Res<MinTerminalSize>,
+    mut force: ResMut<ForceRedraw>,
+    draw_buffers: Query<(Ref<DrawBuffer>, Option<Ref<Blend>>)>,
+    children: Query<Ref<Children>>,
+    layers: Query<(&Layer, Entity, Option<Ref<Dim>>, Option<Ref<Opacity>>)>,
+    mut caches: Query<&mut LayerCache>,
+    order: Query<(Option<&Floating>, Option<&ZIndex>, Option<&Visibility>)>,
+    frames: Query<Ref<FrameBuffer>>,
+    scrolls: Query<(Ref<Scroll>, Ref<Viewport>)>,
+    defaults: Res<DefaultColors>,
+) -> Result<(), FoxinError> {
     let mut layers = layers
         .iter()
         .collect::<Vec<_>>();
-    layers.sort();
+    layers.sort_by(|a, b| (a.0, a.1).cmp(&(b.0, b.1)));
+
+    let area = terminal.0.get_frame().size();
+    if min_size.too_small(area) {
+        // Everything is laid out and drawn again once there's room
+        force.0 = true;
+        min_size.render_message(area, terminal.0.current_buffer_mut());
+        return flush(&mut terminal);
+    }
+    let mut dirty = force.0 || defaults.is_changed();
+
+    for (_, entity, dim, opacity) in layers.iter() {
+        let Ok(mut cache) = caches.get_mut(*entity) else { continue; };
+        let layer_dirty = cache.0.area != area
+            || force.0
+            || any_in_tree(*entity, &children, |entity| {
+                draw_buffers.get(entity).is_ok_and(|(buffer, blend)| {
+                    buffer.is_changed() || blend.is_some_and(|b| b.is_changed())
+                }) || children.get(entity).is_ok_and(|c| c.is_changed())
+                    || frames.get(entity).is_ok_and(|f| f.is_changed())
+                    || scrolls.get(entity).is_ok_and(|(s, v)| s.is_changed() || v.is_changed())
+            });
+        if layer_dirty {
+            render_layer(
+                area,
+                &mut cache,
+                &draw_buffers,
+                &children,
+                &order,
+                &frames,
+                &scrolls,
+                *entity
+            );
+        }
+        dirty |= layer_dirty
+            || dim.as_ref().is_some_and(|d| d.is_changed())
+            || opacity.as_ref().is_some_and(|o| o.is_changed());
+    }
+
+    if !dirty {
+        return Ok(());
+    }
+    force.0 = false;
 
     let mut frame = terminal.0.get_frame();
+    let buffer = frame.buffer_mut();
 
-    for (_, entity) in layers.into_iter() {
-        render_layer(
-            frame.buffer_mut(),
-            &draw_buffers, 
-            &children,
-            entity
-        );
+    for (_, entity, dim, opacity) in layers.into_iter() {
+        let Ok(cache) = caches.get(entity) else { continue; };
+        if order.get(entity).is_ok_and(|(_, _, v)| visibility::is_hidden(v)) {
+            continue;
+        }
+        if let Some(dim) = dim {
+            blend::dim_buffer(buffer, dim.0, &defaults);
+        }
+        let opacity = opacity.map(|o| o.0).unwrap_or(1.0);
+        for (upper, lower) in cache.0.content.iter().zip(buffer.content.iter_mut()) {
+            blend::over(upper, lower, opacity);
+        }
     }
 
-    terminal.0.hide_cursor().unwrap();
-    terminal.0.flush().unwrap();
+    flush(&mut terminal)
+}
+
+fn flush(terminal: &mut Terminal) -> Result<(), FoxinError> {
+    terminal.0.flush().map_err(FoxinError::Terminal)?;
     terminal.0.swap_buffers();
-    terminal.0.backend_mut().flush().unwrap();
+    terminal.0.backend_mut().flush().map_err(FoxinError::Terminal)
 }
 
+#[allow(clippy::too_many_arguments)]
 fn render_layer(
-    buffer: &mut Buffer,
-    draw_buffers: &Query<&DrawBuffer>,
-    children: &Query<&Children>,
+    area: Rect,
+    cache: &mut LayerCache,
+    draw_buffers: &Query<(Ref<DrawBuffer>, Option<Ref<Blend>>)>,
+    children: &Query<Ref<Children>>,
+    order: &Query<(Option<&Floating>, Option<&ZIndex>, Option<&Visibility>)>,
+    frames: &Query<Ref<FrameBuffer>>,
+    scrolls: &Query<(Ref<Scroll>, Ref<Viewport>)>,
     entity: Entity,
 ) {
-    let mut to_render = VecDeque::new();
-    to_render.push_back(entity);
+    cache.0 = Buffer::filled(area, &blend::transparent_cell());
+
+    /// Where a node's buffers go. Descendants of a scroll are moved by its
+    /// offset and cut off at its viewport.
+    #[derive(Clone)]
+    struct Placement {
+        z: Vec<(bool, ZIndex)>,
+        clip: Rect,
+        shift: IVec2,
+    }
 
-    while let Some(entity) = to_render.pop_front() {
-        if let Ok(draw_buffer) = draw_buffers.get(entity) {
-            buffer.merge(&draw_buffer.0);
+    // Every node's z is relative to its parent's, and floating nodes sit a
+    // level above their tiled siblings. Ties keep tree order.
+    let mut nodes = Vec::new();
+    let mut to_visit = VecDeque::new();
+    to_visit.push_back((entity, Placement { z: Vec::new(), clip: area, shift: IVec2::ZERO }));
+
+    while let Some((entity, mut placement)) = to_visit.pop_front() {
+        let (floating, z_index, visibility) = order.get(entity).unwrap_or_default();
+        if visibility::is_hidden(visibility) {
+            continue;
         }
+        placement.z.push((floating.is_some(), z_index.copied().unwrap_or_default()));
 
-        to_render.extend(children
+        let mut inner = placement.clone();
+        if let Ok((scroll, viewport)) = scrolls.get(entity) {
+            inner.clip = inner.clip.intersection(shifted(viewport.area, placement.shift));
+            inner.shift += scroll.clamped_offset(viewport.area).as_ivec2();
+        }
+        to_visit.extend(children
             .get(entity)
-            .ok()
-            .iter()
-            .map(|v| v.iter())
-            .flatten()
+            .map(|v| v.to_vec())
+            .unwrap_or_default()
+            .into_iter()
+            .map(|child| (child, inner.clone()))
         );
+        nodes.push((placement, entity));
+    }
+    nodes.sort_by(|a, b| a.0.z.cmp(&b.0.z));
+
+    for (placement, entity) in nodes {
+        let (draw_buffer, mode) = draw_buffers
+            .get(entity)
+            .map(|(buffer, mode)| (Some(buffer), mode.map(|m| *m)))
+            .unwrap_or_default();
+        let mode = mode.unwrap_or_default();
+        let scrollbar = scrolls
+            .get(entity)
+            .ok()
+            .and_then(|(scroll, viewport)| scroll.render_scrollbar(&viewport));
+        // A frame goes under what it surrounds
+        let buffers = frames.get(entity).ok().map(|f| &f.into_inner().0)
+            .into_iter()
+            .chain(draw_buffer.map(|b| &b.into_inner().0))
+            .chain(scrollbar.as_ref());
+        for buffer in buffers {
+            let target = placement.clip.intersection(shifted(buffer.area, placement.shift));
+            for y in target.top()..target.bottom() {
+                for x in target.left()..target.right() {
+                    let from = IVec2::new(x as i32, y as i32) + placement.shift;
+                    let cell = blend::canonical(buffer.get(from.x as u16, from.y as u16), mode);
+                    blend::over(&cell, cache.0.get_mut(x, y), 1.0);
+                }
+            }
+        }
+    }
+}
+
+/// `rect` moved up and left by `shift`, cut off at the edges of the screen
+fn shifted(rect: Rect, shift: IVec2) -> Rect {
+    let left = (rect.left() as i32 - shift.x).max(0);
+    let top = (rect.top() as i32 - shift.y).max(0);
+    let right = (rect.right() as i32 - shift.x).max(left);
+    let bottom = (rect.bottom() as i32 - shift.y).max(top);
+    Rect {
+        x: left as u16,
+        y: top as u16,
+        width: (right - left) as u16,
+        height: (bottom - top) as u16,
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use bevy::{app::App, ecs::change_detection::DetectChanges};
+    use ratatui::widgets::Paragraph;
+
+    /// An app drawing into a `size` buffer instead of the terminal, with no
+    /// limit on how often
+    pub(crate) fn headless_app(size: U16Vec2) -> App {
+        headless_app_with(crate::Foxin::new(), size)
+    }
+
+    pub(crate) fn headless_app_with(foxin: crate::Foxin, size: U16Vec2) -> App {
+        let mut app = App::new();
+        app.add_plugins(foxin.backend(OutputBackend::Headless(size)).max_fps(0.0));
+        app
+    }
+
+    /// Resize a headless app's terminal the way a real one would, telling
+    /// the app about it
+    pub(crate) fn resize_headless(app: &mut App, size: U16Vec2) {
+        if let FoxinBackend::Headless(backend) = app.world.resource_mut::<Terminal>().0.backend_mut() {
+            backend.resize(size.x, size.y);
+        }
+        app.world.send_event(crate::input::Resize(size));
+    }
+
+    /// Run a frame that's drawn whether or not anything changed
+    pub(crate) fn request_frame(app: &mut App) {
+        let now = app.world.resource::<crate::time::Clock>().now();
+        app.world.resource_mut::<crate::time::RenderTimeout>().by(now);
+        app.update();
+    }
+
+    #[test]
+    fn inline_screens_lay_out_their_rows_only() {
+        let foxin = crate::Foxin::new().screen(Screen::Inline(3));
+        let mut app = headless_app_with(foxin, U16Vec2::new(20, 10));
+        let root = app.world.spawn((Layer(0), DrawArea::default(), FoxinWidget::new(Paragraph::new("inline")))).id();
+        app.update();
+
+        assert!(!app.world.resource::<OutputMode>().alternate_screen);
+        let area = app.world.get::<DrawArea>(root).unwrap().0;
+        assert_eq!((area.width, area.height), (20, 3));
+        let buffer = headless_buffer(&app.world).unwrap();
+        assert_eq!(buffer.get(area.x, area.y).symbol(), "i");
+    }
+
+    #[test]
+    fn unchanged_layers_are_not_composited_again() {
+        let mut app = headless_app(U16Vec2::new(5, 1));
+        let below = app.world.spawn((Layer(0), FoxinWidget::new(Paragraph::new("below")))).id();
+        let above = app.world.spawn((Layer(1), FoxinWidget::new(Paragraph::new("up")))).id();
+        app.update();
+        let cached = |app: &App, layer| app.world.entity(layer).get_ref::<LayerCache>().unwrap().last_changed();
+        let (below_drawn, above_drawn) = (cached(&app, below), cached(&app, above));
+
+        *app.world.get_mut::<FoxinWidget>(above).unwrap() = FoxinWidget::new(Paragraph::new("down"));
+        app.update();
+        assert_eq!(cached(&app, below), below_drawn);
+        assert_ne!(cached(&app, above), above_drawn);
+        assert_eq!(*headless_buffer(&app.world).unwrap(), Buffer::with_lines(vec!["downw"]));
     }
 }
@@ -0,0 +1,498 @@
+use bevy::{
+    app::{App, First},
+    ecs::{
+        schedule::IntoSystemConfigs,
+        system::{IntoSystem, Resource, Res, ResMut},
+        world::World,
+    },
+};
+use crossterm::event::{Event, KeyCode, KeyEventKind};
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::VecDeque,
+    fs::File,
+    io::{BufRead, BufReader, BufWriter, Write},
+    path::{Path, PathBuf},
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
+};
+use crate::{Foxin, error::FoxinError, input::RawInputs, schedule::PostLogic, time::{Clock, RenderTimeout}};
+
+pub(crate) fn build(app: &mut App, foxin: &Foxin) {
+    let player = foxin.playback.as_ref().map(Player::load);
+    let seed = match player {
+        Some(Ok(mut player)) => {
+            let seed = player.seed;
+            // The recording's times drive the clock, and the clock it
+            // replaces sets the pace they are played at
+            let clock = Clock::manual();
+            player.start = clock.now();
+            player.pace = std::mem::replace(app.world.resource_mut::<Clock>().as_mut(), clock);
+            app.insert_resource(player);
+            app.add_systems(First, play_frame
+                .before(crate::time::clear_logic_timeout)
+                .before(crate::input::GatherInput)
+            );
+            seed
+        },
+        Some(Err(err)) => {
+            crate::error::fail(&mut app.world, FoxinError::Playback(err));
+            random_seed()
+        },
+        None => random_seed(),
+    };
+    app.insert_resource(Seed(seed));
+    app.init_resource::<Turn>();
+    app.add_systems(PostLogic, stop_fast_forwarding);
+
+    if let Some(path) = &foxin.record {
+        let now = app.world.resource::<Clock>().now();
+        match Recorder::create(path, seed, now) {
+            Ok(recorder) => {
+                app.insert_resource(recorder);
+                app.add_systems(First, record_inputs
+                    .pipe(crate::error::report)
+                    .after(crate::input::GatherInput)
+                );
+            },
+            Err(err) => crate::error::fail(&mut app.world, FoxinError::Recording(err)),
+        }
+    }
+}
+
+pub(crate) fn cleanup(app: &mut App) {
+    if let Some(mut recorder) = app.world.get_resource_mut::<Recorder>() {
+        std::mem::drop(recorder.file.flush());
+    }
+}
+
+/// Seed for the game's random number generators. It is stored in recordings
+/// and restored on playback. Foxin draws nothing from it itself, so a game
+/// that uses randomness must seed it from `Res<Seed>` to replay exactly.
+#[derive(Resource, Debug, Copy, Clone)]
+pub struct Seed(pub u64);
+
+/// Turns the game has played. Foxin doesn't advance it, games bump it
+/// whenever a turn passes so playback can fast-forward to one.
+#[derive(Resource, Debug, Default, Copy, Clone, PartialEq, Eq)]
+pub struct Turn(pub u64);
+
+#[derive(Debug, Clone)]
+pub struct Playback {
+    pub path: PathBuf,
+    /// Replay without waiting between frames until this [`Turn`] is reached
+    pub fast_forward_to: Option<u64>,
+    /// Pressing this in the terminal quits during playback, since other
+    /// input is ignored until the recording runs out
+    pub quit_key: KeyCode,
+}
+
+impl Playback {
+    pub fn new(path: impl Into<PathBuf>) -> Self {
+        Self {
+            path: path.into(),
+            fast_forward_to: None,
+            quit_key: KeyCode::Esc,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+struct Header {
+    seed: u64,
+}
+
+#[derive(Serialize, Deserialize)]
+struct RecordedFrame {
+    frame: u64,
+    time: Duration,
+    events: Vec<Event>,
+}
+
+#[derive(Resource)]
+struct Recorder {
+    file: BufWriter<File>,
+    started: Instant,
+}
+
+impl Recorder {
+    fn create(path: &Path, seed: u64, now: Instant) -> std::io::Result<Self> {
+        let mut file = BufWriter::new(File::create(path)?);
+        serde_json::to_writer(&mut file, &Header { seed })?;
+        file.write_all(b"\n")?;
+        Ok(Self {
+            file,
+            started: now,
+        })
+    }
+
+    fn write(&mut self, inputs: &RawInputs, now: Instant) -> std::io::Result<()> {
+        let frame = RecordedFrame {
+            frame: inputs.frame(),
+            time: now.saturating_duration_since(self.started),
+            events: inputs.events().cloned().collect(),
+        };
+        serde_json::to_writer(&mut self.file, &frame)?;
+        self.file.write_all(b"\n")?;
+        // Recordings are mostly wanted after a crash, so don't sit on them
+        self.file.flush()
+    }
+}
+
+#[derive(Resource)]
+pub(crate) struct Player {
+    seed: u64,
+    frames: VecDeque<RecordedFrame>,
+    fast_forward_to: Option<u64>,
+    quit_key: KeyCode,
+    /// Events of the frame last played, until input is gathered
+    pending: Option<Vec<Event>>,
+    /// What the clock read at recorded time zero
+    start: Instant,
+    /// The clock the recording is played back at
+    pace: Clock,
+    /// The real instant recorded time zero maps to
+    real_base: Option<Instant>,
+    /// Recorded time of the frame last played
+    played_to: Duration,
+    /// Whether the clock has been handed back after the last frame
+    done: bool,
+}
+
+impl Player {
+    fn load(playback: &Playback) -> std::io::Result<Self> {
+        let mut lines = BufReader::new(File::open(&playback.path)?).lines();
+        let header: Header = match lines.next() {
+            Some(line) => serde_json::from_str(&line?)?,
+            None => return Err(std::io::ErrorKind::UnexpectedEof.into()),
+        };
+        let frames = lines
+            .map(|line| Ok(serde_json::from_str(&line?)?))
+            .collect::<std::io::Result<VecDeque<_>>>()?;
+
+        Ok(Self {
+            seed: header.seed,
+            frames,
+            fast_forward_to: playback.fast_forward_to,
+            quit_key: playback.quit_key,
+            pending: None,
+            start: Instant::now(),
+            pace: Clock::real(),
+            real_base: None,
+            played_to: Duration::ZERO,
+            done: false,
+        })
+    }
+
+    pub(crate) fn is_playing(&self) -> bool {
+        !self.frames.is_empty() || self.pending.is_some()
+    }
+
+    fn fast_forwarding(&self) -> bool {
+        self.fast_forward_to.is_some() && self.is_playing()
+    }
+
+    /// Whether the terminal input read while playing asks to quit
+    pub(crate) fn wants_quit(&self, terminal: &[Event]) -> bool {
+        terminal.iter().any(|event| matches!(
+            event,
+            Event::Key(key) if key.code == self.quit_key && key.kind == KeyEventKind::Press
+        ))
+    }
+
+    /// How long in real time until the next recorded frame is due, or
+    /// `None` once the recording is over
+    pub(crate) fn real_wait(&self, real_now: Instant) -> Option<Duration> {
+        if self.done {
+            return None;
+        }
+        // The frame after the last one hands the clock back
+        let Some(next) = self.frames.front() else {
+            return Some(Duration::ZERO);
+        };
+        let Some(base) = self.real_base.filter(|_| !self.fast_forwarding()) else {
+            return Some(Duration::ZERO);
+        };
+        Some((base + self.pace.real_duration(next.time)).saturating_duration_since(real_now))
+    }
+
+    /// Play the next recorded frame if it's due, returning its recorded time.
+    /// Its events wait in [`take_pending`](Self::take_pending).
+    fn play(&mut self, real_now: Instant) -> Option<Duration> {
+        if !self.real_wait(real_now)?.is_zero() {
+            return None;
+        }
+        let recorded = self.frames.pop_front()?;
+        if self.real_base.is_none() {
+            self.restart_pacing(real_now, recorded.time);
+        }
+        self.played_to = recorded.time;
+        self.pending.get_or_insert_with(Vec::new).extend(recorded.events);
+        Some(recorded.time)
+    }
+
+    /// Events of the frames played since this was last called
+    pub(crate) fn take_pending(&mut self) -> Option<Vec<Event>> {
+        self.pending.take()
+    }
+
+    /// Carry on at the recorded pace from `recorded` being `real_now`
+    fn restart_pacing(&mut self, real_now: Instant, recorded: Duration) {
+        let elapsed = self.pace.real_duration(recorded);
+        self.real_base = Some(real_now.checked_sub(elapsed).unwrap_or(real_now));
+    }
+}
+
+fn random_seed() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_nanos() as u64)
+        .unwrap_or_default()
+        ^ std::process::id() as u64
+}
+
+fn record_inputs(
+    inputs: Res<RawInputs>,
+    mut recorder: ResMut<Recorder>,
+    clock: Res<Clock>,
+) -> Result<(), FoxinError> {
+    recorder.write(&inputs, clock.now()).map_err(FoxinError::Recording)
+}
+
+/// Move the clock to the time of the next recorded frame once it's due
+fn play_frame(mut player: ResMut<Player>, mut clock: ResMut<Clock>) {
+    if let Some(time) = player.play(Instant::now()) {
+        clock.set(player.start + time);
+    } else if !player.is_playing() && !player.done {
+        // Time carries on from the end of the recording
+        player.done = true;
+        *clock = player.pace.continue_from(clock.now());
+    }
+}
+
+/// Nothing is drawn while fast forwarding, so the frame it stops on is
+fn stop_fast_forwarding(
+    turn: Res<Turn>,
+    player: Option<ResMut<Player>>,
+    mut render_timeout: ResMut<RenderTimeout>,
+    clock: Res<Clock>,
+) {
+    let Some(mut player) = player else { return; };
+    let Some(to) = player.fast_forward_to else { return; };
+    if turn.0 >= to || !player.is_playing() {
+        player.fast_forward_to = None;
+        let played_to = player.played_to;
+        player.restart_pacing(Instant::now(), played_to);
+        render_timeout.by(clock.now());
+    }
+}
+
+/// Whether playback is skipping ahead, which draws nothing
+pub(crate) fn fast_forwarding(world: &World) -> bool {
+    world
+        .get_resource::<Player>()
+        .is_some_and(Player::fast_forwarding)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bevy::{
+        ecs::{event::{Event as BevyEvent, EventReader}, system::RunSystemOnce},
+        math::U16Vec2,
+    };
+    use crossterm::event::{KeyEvent, KeyModifiers};
+    use crate::{
+        input::{KeyPress, KeyRelease},
+        render::tests::headless_app_with,
+        schedule::PreLogic,
+        timer::Timers,
+    };
+
+    fn key(code: KeyCode) -> Event {
+        Event::Key(KeyEvent::new(code, KeyModifiers::NONE))
+    }
+
+    fn ms(ms: u64) -> Duration {
+        Duration::from_millis(ms)
+    }
+
+    fn temp_path(name: &str) -> PathBuf {
+        std::env::temp_dir().join(format!("foxin-{name}-{}.jsonl", std::process::id()))
+    }
+
+    fn write_recording(path: &Path, frames: &[(Duration, Vec<Event>)]) {
+        let start = Instant::now();
+        let mut recorder = Recorder::create(path, 42, start).unwrap();
+        let mut inputs = RawInputs::default();
+        for (time, events) in frames {
+            inputs.reset();
+            inputs.raw = events.clone();
+            recorder.write(&inputs, start + *time).unwrap();
+        }
+    }
+
+    fn player(times: impl IntoIterator<Item = Duration>) -> Player {
+        Player {
+            seed: 0,
+            frames: times.into_iter().enumerate().map(|(frame, time)| RecordedFrame {
+                frame: frame as u64,
+                time,
+                events: Vec::new(),
+            }).collect(),
+            fast_forward_to: None,
+            quit_key: KeyCode::Esc,
+            pending: None,
+            start: Instant::now(),
+            pace: Clock::real(),
+            real_base: None,
+            played_to: Duration::ZERO,
+            done: false,
+        }
+    }
+
+    #[test]
+    fn recordings_play_back_as_recorded() {
+        let path = temp_path("replay");
+        let step = ms(100);
+        let recorded = [
+            vec![],
+            vec![key(KeyCode::Char('l')), Event::FocusLost],
+            vec![Event::Resize(80, 24)],
+        ];
+        let frames = recorded.iter().enumerate()
+            .map(|(i, events)| (step * i as u32, events.clone()))
+            .collect::<Vec<_>>();
+        write_recording(&path, &frames);
+
+        let loaded = Player::load(&Playback::new(&path));
+        std::fs::remove_file(&path).unwrap();
+        let mut player = loaded.unwrap();
+        assert_eq!(player.seed, 42);
+
+        // Played back a second later, at the same pace
+        let later = Instant::now() + Duration::from_secs(1);
+        for (i, events) in recorded.into_iter().enumerate() {
+            let due = later + step * i as u32;
+            if i > 0 {
+                assert_eq!(player.real_wait(due - ms(1)), Some(ms(1)));
+                assert_eq!(player.play(due - ms(1)), None);
+            }
+            assert_eq!(player.play(due), Some(step * i as u32));
+            assert_eq!(player.take_pending(), Some(events));
+        }
+        assert!(!player.is_playing());
+        assert_eq!(player.real_wait(later), Some(Duration::ZERO));
+    }
+
+    #[test]
+    fn fast_forwarding_stops_at_the_turn() {
+        let mut player = player([ms(0), ms(1000), ms(2000)]);
+        player.fast_forward_to = Some(2);
+        let start = Instant::now();
+        assert_eq!(player.play(start), Some(ms(0)));
+        assert_eq!(player.real_wait(start), Some(Duration::ZERO));
+        assert_eq!(player.play(start), Some(ms(1000)));
+
+        let mut world = World::new();
+        world.insert_resource(Turn(2));
+        world.insert_resource(Clock::manual());
+        world.init_resource::<RenderTimeout>();
+        world.insert_resource(player);
+        assert!(fast_forwarding(&world));
+        world.run_system_once(stop_fast_forwarding);
+        assert!(!fast_forwarding(&world));
+
+        // Real time pacing picks up from the frame it stopped on
+        let player = world.remove_resource::<Player>().unwrap();
+        let wait = player.real_wait(Instant::now()).unwrap();
+        assert!(wait > ms(500) && wait <= ms(1000));
+        assert!(player.wants_quit(&[key(KeyCode::Esc)]));
+        assert!(!player.wants_quit(&[key(KeyCode::Char('q'))]));
+    }
+
+    #[derive(BevyEvent, Clone)]
+    struct Ping;
+
+    #[derive(Resource, Default)]
+    struct Log(Vec<(Duration, String)>);
+
+    /// Logs input as it arrives, and pings a while after `b` is pressed
+    fn log_inputs(
+        mut presses: EventReader<KeyPress>,
+        mut releases: EventReader<KeyRelease>,
+        mut pings: EventReader<Ping>,
+        mut timers: ResMut<Timers>,
+        mut log: ResMut<Log>,
+        player: Res<Player>,
+        clock: Res<Clock>,
+    ) {
+        let now = clock.now();
+        let at = now.saturating_duration_since(player.start);
+        for press in presses.read() {
+            if press.code == KeyCode::Char('b') {
+                timers.once(now + ms(300), Ping);
+            }
+            log.0.push((at, format!("{press:?}")));
+        }
+        for release in releases.read() {
+            log.0.push((at, format!("{release:?}")));
+        }
+        for _ in pings.read() {
+            log.0.push((at, "ping".to_string()));
+        }
+    }
+
+    fn play(playback: Playback, foxin: Foxin) -> Vec<(Duration, String)> {
+        let mut app = headless_app_with(foxin.playback(playback), U16Vec2::new(10, 2));
+        app.add_event::<Ping>();
+        app.init_resource::<Log>();
+        app.add_systems(PreLogic, log_inputs);
+        app.update();
+        while app.world.resource::<Player>().is_playing() {
+            let sleep = crate::time::run_max_sleep(&mut app.world);
+            std::thread::sleep(sleep);
+            app.update();
+        }
+        assert!(!crate::error::failed(&app.world));
+        std::mem::take(&mut app.world.resource_mut::<Log>().0)
+    }
+
+    #[test]
+    fn playback_times_input_as_recorded() {
+        let source = temp_path("replay-source");
+        let copy = temp_path("replay-copy");
+        write_recording(&source, &[
+            (ms(0), vec![]),
+            (ms(100), vec![key(KeyCode::Char('a'))]),
+            (ms(400), vec![key(KeyCode::Char('a'))]),
+            // The hold timed out at 1000, but nothing ran until now
+            (ms(1100), vec![]),
+            (ms(1500), vec![key(KeyCode::Char('b'))]),
+            (ms(2000), vec![]),
+        ]);
+
+        // Record a copy while fast forwarding through the source, then play
+        // the copy back at a real pace, sped up to keep the test quick
+        let mut fast = Playback::new(&source);
+        fast.fast_forward_to = Some(u64::MAX);
+        let first = play(fast, Foxin::new().record(&copy));
+        let second = play(Playback::new(&copy), Foxin::new().clock(Clock::scaled(20.0)));
+        std::fs::remove_file(&source).unwrap();
+        std::fs::remove_file(&copy).unwrap();
+
+        let press = |code, repeat| format!("{:?}", KeyPress { code, modifiers: KeyModifiers::NONE, repeat });
+        let release = format!("{:?}", KeyRelease {
+            code: KeyCode::Char('a'),
+            modifiers: KeyModifiers::NONE,
+            held_for: ms(300),
+        });
+        assert_eq!(first, vec![
+            (ms(100), press(KeyCode::Char('a'), false)),
+            (ms(400), press(KeyCode::Char('a'), false)),
+            (ms(1100), release),
+            (ms(1500), press(KeyCode::Char('b'), false)),
+            (ms(2000), "ping".to_string()),
+        ]);
+        assert_eq!(second, first);
+    }
+}
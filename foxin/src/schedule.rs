@@ -5,8 +5,9 @@ use bevy::{
         world::World,
     },
 };
+use crate::diagnostics::{self, run_timed};
 
-pub(crate) fn build(app: &mut App) {
+pub(crate) fn build(app: &mut App, _: &crate::Foxin) {
     app.init_schedule(PreLayout);
     app.init_schedule(Layout);
     app.init_schedule(MidRender);
@@ -50,15 +51,25 @@ pub struct Logic;
 pub struct PostLogic;
 
 fn run_schedule(world: &mut World) {
-    world.run_schedule(PreLogic);
-    world.run_schedule(Logic);
-    world.run_schedule(PostLogic);
-
-    if world.run_system(*crate::time::SHOULD_RENDER_SYSTEM.get().unwrap()).unwrap() {
-        world.run_schedule(PreLayout);
-        world.run_schedule(Layout);
-        world.run_schedule(MidRender);
-        world.run_schedule(Render);
-        world.run_schedule(PostRender);
+    run_timed(world, PreLogic, |t| &mut t.pre_logic);
+    run_timed(world, Logic, |t| &mut t.logic);
+    run_timed(world, PostLogic, |t| &mut t.post_logic);
+
+    // Drawing waits until resuming, which redraws everything anyway, and
+    // until playback has fast forwarded as far as it was asked to
+    let skip = crate::suspend::is_suspended(world) || crate::replay::fast_forwarding(world);
+    if !skip && crate::time::run_should_render(world) {
+        diagnostics::start_render(world);
+        run_timed(world, PreLayout, |t| &mut t.pre_layout);
+        // Nothing is laid out in a terminal that's too small, so there's
+        // nothing for the render systems to draw into
+        if !crate::render::too_small(world) {
+            run_timed(world, Layout, |t| &mut t.layout);
+            run_timed(world, MidRender, |t| &mut t.mid_render);
+            run_timed(world, Render, |t| &mut t.render);
+        }
+        run_timed(world, PostRender, |t| &mut t.post_render);
+        crate::render::rendered(world);
+        diagnostics::end_render(world);
     }
 }
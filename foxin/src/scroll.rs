@@ -0,0 +1,315 @@
+use bevy::{
+    app::App,
+    ecs::{
+        change_detection::DetectChangesMut,
+        component::Component,
+        entity::Entity,
+        event::EventReader,
+        query::{With, Without},
+        system::{Commands, Query, Res},
+    },
+    hierarchy::Parent,
+    math::{I16Vec2, IVec2, U16Vec2},
+};
+use crossterm::event::KeyCode;
+use ratatui::{
+    buffer::Buffer,
+    layout::Rect,
+    widgets::{Scrollbar, ScrollbarOrientation, ScrollbarState, StatefulWidget},
+};
+use crate::{
+    cursor::FocusedEntity,
+    input::{KeyPress, MouseScroll},
+    render::RenderAppExt,
+    visibility::{self, Visibility},
+};
+
+/// Lines moved per notch of the mouse wheel
+const WHEEL_LINES: i16 = 3;
+
+pub(crate) fn build(app: &mut App, _: &crate::Foxin) {
+    app.redraw_on_change::<Scroll>()
+        .redraw_on_change::<Viewport>();
+    app.add_systems(crate::schedule::PreLayout, add_viewports);
+    app.add_systems(crate::schedule::PreLogic, (scroll_on_keys, scroll_on_mouse));
+}
+
+pub(crate) fn cleanup(_: &mut App) {}
+
+/// Lays an entity's children out in an area of `content` size and shows the
+/// part of it at `offset`. Children's [`DrawArea`](crate::render::DrawArea)s
+/// are in content coordinates, where the top left of the content is the top
+/// left of the [`Viewport`].
+#[derive(Component, Debug, Default, Copy, Clone, PartialEq, Eq)]
+pub struct Scroll {
+    /// Zero in either direction means the same as the viewport
+    pub content: U16Vec2,
+    pub offset: U16Vec2,
+    /// Draw a scrollbar in the rightmost column when content is taller than
+    /// the viewport
+    pub scrollbar: bool,
+}
+
+impl Scroll {
+    /// Scrolls up and down through `height` lines as wide as the viewport
+    pub fn vertical(height: u16) -> Self {
+        Self {
+            content: U16Vec2::new(0, height),
+            offset: U16Vec2::ZERO,
+            scrollbar: true,
+        }
+    }
+
+    pub fn content_size(&self, viewport: Rect) -> U16Vec2 {
+        let or_viewport = |len: u16, viewport: u16| if len == 0 { viewport } else { len };
+        U16Vec2::new(
+            or_viewport(self.content.x, viewport.width),
+            or_viewport(self.content.y, viewport.height),
+        )
+    }
+
+    pub fn max_offset(&self, viewport: Rect) -> U16Vec2 {
+        self.content_size(viewport)
+            .saturating_sub(U16Vec2::new(viewport.width, viewport.height))
+    }
+
+    /// `offset`, kept within the content. Offsets aren't clamped when the
+    /// viewport shrinks, so going back to the old size shows the same lines.
+    pub fn clamped_offset(&self, viewport: Rect) -> U16Vec2 {
+        self.offset.min(self.max_offset(viewport))
+    }
+
+    pub fn scroll_by(&mut self, delta: I16Vec2, viewport: Rect) {
+        let offset = self.clamped_offset(viewport).as_ivec2() + delta.as_ivec2();
+        self.offset = offset
+            .clamp(IVec2::ZERO, self.max_offset(viewport).as_ivec2())
+            .as_u16vec2();
+    }
+
+    /// Split the area within an entity's frame into its viewport and
+    /// scrollbar
+    pub(crate) fn split(&self, area: Rect) -> (Rect, Option<Rect>) {
+        if !self.scrollbar || self.content.y <= area.height || area.width < 2 {
+            return (area, None);
+        }
+        let viewport = Rect { width: area.width - 1, ..area };
+        let bar = Rect { x: viewport.right(), width: 1, ..area };
+        (viewport, Some(bar))
+    }
+
+    /// The content area children are laid out in
+    pub(crate) fn content_area(&self, viewport: Rect) -> Rect {
+        let size = self.content_size(viewport);
+        Rect {
+            width: size.x.min(u16::MAX - viewport.x),
+            height: size.y.min(u16::MAX - viewport.y),
+            ..viewport
+        }
+    }
+
+    pub(crate) fn render_scrollbar(&self, viewport: &Viewport) -> Option<Buffer> {
+        let bar = viewport.scrollbar?;
+        let mut buffer = Buffer::filled(bar, &crate::blend::transparent_cell());
+        let mut state = ScrollbarState::new(self.max_offset(viewport.area).y as usize + 1)
+            .position(self.clamped_offset(viewport.area).y as usize)
+            .viewport_content_length(viewport.area.height as usize);
+        Scrollbar::new(ScrollbarOrientation::VerticalRight)
+            .begin_symbol(None)
+            .end_symbol(None)
+            .render(bar, &mut buffer, &mut state);
+        Some(buffer)
+    }
+}
+
+/// Where a [`Scroll`]'s content shows, set by layout. Nested in another
+/// scroll, this is in that scroll's content coordinates.
+#[derive(Component, Debug, Default, Copy, Clone, PartialEq, Eq)]
+pub struct Viewport {
+    pub area: Rect,
+    pub(crate) scrollbar: Option<Rect>,
+    /// The part of `area` that shows on screen, in terminal coordinates
+    pub(crate) on_screen: Rect,
+}
+
+/// Keys that scroll an entity's [`Scroll`] up and down while it, or something
+/// in it, is the [`FocusedEntity`](crate::cursor::FocusedEntity)
+#[derive(Component, Debug, Copy, Clone, PartialEq, Eq)]
+pub struct ScrollKeys {
+    pub up: KeyCode,
+    pub down: KeyCode,
+    pub page_up: KeyCode,
+    pub page_down: KeyCode,
+    pub top: KeyCode,
+    pub bottom: KeyCode,
+}
+
+impl Default for ScrollKeys {
+    fn default() -> Self {
+        Self {
+            up: KeyCode::Up,
+            down: KeyCode::Down,
+            page_up: KeyCode::PageUp,
+            page_down: KeyCode::PageDown,
+            top: KeyCode::Home,
+            bottom: KeyCode::End,
+        }
+    }
+}
+
+fn add_viewports(
+    mut commands: Commands,
+    scrolls: Query<Entity, (With<Scroll>, Without<Viewport>)>,
+) {
+    for entity in scrolls.iter() {
+        commands.entity(entity).insert(Viewport::default());
+    }
+}
+
+fn scroll_on_keys(
+    mut presses: EventReader<KeyPress>,
+    focused: Res<FocusedEntity>,
+    parents: Query<&Parent>,
+    visibilities: Query<&Visibility>,
+    mut scrolls: Query<(&mut Scroll, &Viewport, &ScrollKeys)>,
+) {
+    // The focused entity and everything it's in
+    let focused = std::iter::successors(focused.0, |e| parents.get(*e).ok().map(Parent::get))
+        .filter(|e| !visibility::is_hidden_in_tree(*e, &parents, &visibilities))
+        .collect::<Vec<_>>();
+    for press in presses.read() {
+        for entity in focused.iter() {
+            let Ok((mut scroll, viewport, keys)) = scrolls.get_mut(*entity) else { continue; };
+            let page = viewport.area.height.saturating_sub(1).max(1) as i16;
+            let lines = match press.code {
+                code if code == keys.up => -1,
+                code if code == keys.down => 1,
+                code if code == keys.page_up => -page,
+                code if code == keys.page_down => page,
+                code if code == keys.top => i16::MIN,
+                code if code == keys.bottom => i16::MAX,
+                _ => continue,
+            };
+            let mut next = *scroll;
+            next.scroll_by(I16Vec2::new(0, lines), viewport.area);
+            scroll.set_if_neq(next);
+        }
+    }
+}
+
+fn scroll_on_mouse(
+    mut wheel: EventReader<MouseScroll>,
+    parents: Query<&Parent>,
+    visibilities: Query<&Visibility>,
+    mut scrolls: Query<(Entity, &mut Scroll, &Viewport)>,
+) {
+    for event in wheel.read() {
+        // Only the innermost viewport under the mouse scrolls. Hidden ones
+        // weren't laid out, so their viewports are out of date.
+        let target = scrolls
+            .iter_mut()
+            .filter(|(_, _, viewport)| contains(viewport.on_screen, event.position))
+            .filter(|(entity, _, _)| !visibility::is_hidden_in_tree(*entity, &parents, &visibilities))
+            .min_by_key(|(_, _, viewport)| viewport.on_screen.area());
+        if let Some((_, mut scroll, viewport)) = target {
+            let mut next = *scroll;
+            next.scroll_by(event.delta * WHEEL_LINES, viewport.area);
+            scroll.set_if_neq(next);
+        }
+    }
+}
+
+fn contains(area: Rect, pos: U16Vec2) -> bool {
+    (area.left()..area.right()).contains(&pos.x) && (area.top()..area.bottom()).contains(&pos.y)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bevy::hierarchy::BuildWorldChildren;
+    use ratatui::widgets::Paragraph;
+    use crate::render::{Constraint, FoxinWidget, Layer, tests::headless_app};
+
+    #[test]
+    fn offsets_stay_within_the_content() {
+        let mut scroll = Scroll::vertical(10);
+        let viewport = Rect::new(0, 0, 5, 4);
+        scroll.scroll_by(I16Vec2::new(0, 100), viewport);
+        assert_eq!(scroll.offset, U16Vec2::new(0, 6));
+        // Growing the viewport shows more without forgetting the offset
+        let taller = Rect::new(0, 0, 5, 8);
+        assert_eq!(scroll.clamped_offset(taller), U16Vec2::new(0, 2));
+        assert_eq!(scroll.offset, U16Vec2::new(0, 6));
+        scroll.scroll_by(I16Vec2::new(0, -1), taller);
+        assert_eq!(scroll.offset, U16Vec2::new(0, 1));
+        scroll.scroll_by(I16Vec2::new(0, i16::MIN), viewport);
+        assert_eq!(scroll.offset, U16Vec2::ZERO);
+    }
+
+    fn wheel(app: &mut App, position: U16Vec2, lines: i16) {
+        app.world.send_event(MouseScroll { position, delta: I16Vec2::new(0, lines) });
+        app.update();
+    }
+
+    #[test]
+    fn the_wheel_scrolls_whatever_is_under_it_on_screen() {
+        let mut app = headless_app(U16Vec2::new(10, 6));
+        let unscrolled = Scroll { scrollbar: false, ..Scroll::vertical(12) };
+        let mut inner = Entity::PLACEHOLDER;
+        let outer = app.world.spawn((Layer(0), unscrolled)).with_children(|outer| {
+            outer.spawn(Constraint(ratatui::layout::Constraint::Length(6)));
+            inner = outer.spawn((
+                Constraint(ratatui::layout::Constraint::Length(4)),
+                Scroll { scrollbar: false, ..Scroll::vertical(10) },
+            )).with_children(|inner| {
+                inner.spawn(FoxinWidget::new(Paragraph::new("inner")));
+            }).id();
+        }).id();
+        app.update();
+        let offset = |app: &App, entity| app.world.get::<Scroll>(entity).unwrap().offset.y;
+
+        // The inner scroll sits below the outer one's first screen
+        wheel(&mut app, U16Vec2::new(1, 1), 2);
+        assert_eq!((offset(&app, outer), offset(&app, inner)), (6, 0));
+        app.update();
+
+        // and is now at the top of it
+        wheel(&mut app, U16Vec2::new(1, 1), 1);
+        assert_eq!((offset(&app, outer), offset(&app, inner)), (6, 3));
+        wheel(&mut app, U16Vec2::new(1, 5), -1);
+        assert_eq!((offset(&app, outer), offset(&app, inner)), (3, 3));
+
+        // Hidden scrolls don't take the wheel from what's around them
+        app.world.entity_mut(inner).insert(crate::visibility::Visibility::Hidden);
+        app.update();
+        wheel(&mut app, U16Vec2::new(1, 4), 1);
+        assert_eq!((offset(&app, outer), offset(&app, inner)), (6, 3));
+    }
+
+    #[test]
+    fn keys_only_scroll_the_focused_entity() {
+        let mut app = headless_app(U16Vec2::new(10, 4));
+        let spawn_scroll = |world: &mut bevy::ecs::world::World| world
+            .spawn((Layer(0), Scroll::vertical(10), ScrollKeys::default()))
+            .id();
+        let focused = spawn_scroll(&mut app.world);
+        let other = spawn_scroll(&mut app.world);
+        app.world.insert_resource(FocusedEntity(Some(focused)));
+        app.update();
+        let press = |app: &mut App| {
+            app.world.send_event(KeyPress {
+                code: KeyCode::Down,
+                modifiers: crossterm::event::KeyModifiers::NONE,
+                repeat: false,
+            });
+            app.update();
+        };
+        let offset = |app: &App, entity| app.world.get::<Scroll>(entity).unwrap().offset.y;
+
+        press(&mut app);
+        assert_eq!((offset(&app, focused), offset(&app, other)), (1, 0));
+        app.world.entity_mut(focused).insert(crate::visibility::Visibility::Hidden);
+        app.update();
+        press(&mut app);
+        assert_eq!((offset(&app, focused), offset(&app, other)), (1, 0));
+    }
+}
@@ -0,0 +1,243 @@
+use bevy::{
+    app::App,
+    ecs::{
+        system::Resource,
+        world::World,
+    },
+};
+use crate::{
+    error::FoxinError,
+    input::{KeysHeld, TerminalFeatures},
+    render::OutputMode,
+};
+
+pub(crate) fn build(app: &mut App, foxin: &crate::Foxin) {
+    app.init_resource::<Suspended>();
+
+    // Without a terminal there's no job control to take part in
+    #[cfg(unix)]
+    if !crate::render::OutputMode::of(foxin).headless {
+        unix::build(app);
+    }
+}
+
+pub(crate) fn cleanup(_: &mut App) {}
+
+/// Set between [`suspend`] and [`resume`]. Nothing is read from or drawn to
+/// the terminal meanwhile.
+#[derive(Resource, Default)]
+pub(crate) struct Suspended(bool);
+
+impl Suspended {
+    pub(crate) fn is_suspended(&self) -> bool {
+        self.0
+    }
+}
+
+pub(crate) fn is_suspended(world: &World) -> bool {
+    world
+        .get_resource::<Suspended>()
+        .is_some_and(Suspended::is_suspended)
+}
+
+/// Whether Ctrl-Z from outside, or SIGCONT, can arrive while the runner waits
+pub(crate) fn watching_signals(world: &World) -> bool {
+    #[cfg(unix)]
+    return world.contains_resource::<unix::Signals>();
+    #[cfg(not(unix))]
+    return false;
+}
+
+/// Whether a job control signal arrived that hasn't been handled yet
+pub(crate) fn signal_pending(world: &World) -> bool {
+    #[cfg(unix)]
+    return unix::signal_pending(world);
+    #[cfg(not(unix))]
+    return false;
+}
+
+/// Hand the terminal back to whoever had it before us, e.g. to run an
+/// external editor. Undone by [`resume`].
+pub fn suspend(world: &mut World) -> Result<(), FoxinError> {
+    let mut suspended = world.resource_mut::<Suspended>();
+    if suspended.0 {
+        return Ok(());
+    }
+    suspended.0 = true;
+
+    let enhanced = world
+        .get_resource::<KeysHeld>()
+        .map(|held| held.reports_releases())
+        .unwrap_or(false);
+    let features = *world.resource::<TerminalFeatures>();
+    let mode = *world.resource::<OutputMode>();
+    let disabled = crate::input::disable_terminal_features(&features, enhanced, mode);
+    let left = crate::render::leave_terminal(mode);
+    disabled.and(left).map_err(FoxinError::Terminal)
+}
+
+/// Take the terminal back after [`suspend`] and redraw everything
+pub fn resume(world: &mut World) -> Result<(), FoxinError> {
+    let mut suspended = world.resource_mut::<Suspended>();
+    if !suspended.0 {
+        return Ok(());
+    }
+    suspended.0 = false;
+
+    let enhanced = world
+        .get_resource::<KeysHeld>()
+        .map(|held| held.reports_releases())
+        .unwrap_or(false);
+    let mode = *world.resource::<OutputMode>();
+    crate::render::enter_terminal(mode).map_err(FoxinError::Terminal)?;
+    let features = TerminalFeatures {
+        keyboard_enhancement: enhanced,
+        ..*world.resource::<TerminalFeatures>()
+    };
+    crate::input::enable_terminal_features(&features, mode).map_err(FoxinError::Terminal)?;
+    crate::render::full_redraw(world).map_err(FoxinError::Terminal)
+}
+
+#[cfg(unix)]
+mod unix {
+    use bevy::{
+        app::{App, First},
+        ecs::{
+            schedule::IntoSystemConfigs,
+            system::Resource,
+            world::World,
+        },
+    };
+    use crossterm::event::{Event, KeyCode, KeyEvent, KeyEventKind, KeyModifiers};
+    use signal_hook::{
+        consts::{SIGCONT, SIGSTOP, SIGTSTP},
+        flag,
+        low_level::raise,
+    };
+    use std::sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    };
+    use crate::{
+        error::{fail, FoxinError},
+        input::{GatherInput, RawInputs},
+    };
+
+    pub(super) fn build(app: &mut App) {
+        let signals = Signals::default();
+        let registered = flag::register(SIGTSTP, signals.stop.clone())
+            .and_then(|_| flag::register(SIGCONT, signals.cont.clone()));
+        match registered {
+            Ok(_) => { app.insert_resource(signals); },
+            Err(err) => fail(&mut app.world, FoxinError::Terminal(err)),
+        }
+        app.add_systems(First, handle_job_control.after(GatherInput));
+    }
+
+    /// Raw mode stops the terminal turning Ctrl-Z into SIGTSTP, and we catch
+    /// SIGTSTP ourselves so the terminal can be restored before stopping.
+    #[derive(Resource, Default)]
+    pub(super) struct Signals {
+        stop: Arc<AtomicBool>,
+        cont: Arc<AtomicBool>,
+    }
+
+    pub(super) fn signal_pending(world: &World) -> bool {
+        world
+            .get_resource::<Signals>()
+            .is_some_and(|signals| {
+                signals.stop.load(Ordering::Relaxed) || signals.cont.load(Ordering::Relaxed)
+            })
+    }
+
+    /// Whether Ctrl-Z was pressed in the terminal. A recording playing back
+    /// can't suspend us, whatever it holds.
+    pub(super) fn ctrl_z(inputs: &RawInputs) -> bool {
+        inputs.terminal.iter().any(|event| matches!(
+            event,
+            Event::Key(KeyEvent {
+                code: KeyCode::Char('z'),
+                kind: KeyEventKind::Press,
+                modifiers,
+                ..
+            }) if modifiers.contains(KeyModifiers::CONTROL)
+        ))
+    }
+
+    fn handle_job_control(world: &mut World) {
+        let ctrl_z = ctrl_z(world.resource::<RawInputs>());
+        let (stop, cont) = world
+            .get_resource::<Signals>()
+            .map(|signals| (
+                signals.stop.swap(false, Ordering::Relaxed),
+                signals.cont.swap(false, Ordering::Relaxed),
+            ))
+            .unwrap_or_default();
+
+        if ctrl_z || stop {
+            if let Err(err) = super::suspend(world) {
+                fail(world, err);
+                return;
+            }
+            // Blocks until we get SIGCONT
+            std::mem::drop(raise(SIGSTOP));
+            if let Some(signals) = world.get_resource::<Signals>() {
+                signals.cont.store(false, Ordering::Relaxed);
+            }
+            if let Err(err) = super::resume(world) {
+                fail(world, err);
+            }
+        } else if cont && !super::is_suspended(world) {
+            // Someone else stopped us, so whatever is on screen is suspect
+            if let Err(err) = crate::render::full_redraw(world) {
+                fail(world, FoxinError::Terminal(err));
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bevy::math::U16Vec2;
+    use ratatui::widgets::Paragraph;
+    use crate::render::{self, tests::{headless_app, request_frame}};
+
+    #[test]
+    fn nothing_is_drawn_while_suspended() {
+        let mut app = headless_app(U16Vec2::new(6, 1));
+        let widget = app.world.spawn((
+            render::Layer(0),
+            render::FoxinWidget::new(Paragraph::new("before")),
+        )).id();
+        app.update();
+
+        suspend(&mut app.world).unwrap();
+        assert!(is_suspended(&app.world));
+        app.world.entity_mut(widget).insert(render::FoxinWidget::new(Paragraph::new("after")));
+        request_frame(&mut app);
+        let buffer = render::headless_buffer(&app.world).unwrap();
+        assert_eq!(*buffer, ratatui::buffer::Buffer::with_lines(vec!["before"]));
+
+        resume(&mut app.world).unwrap();
+        assert!(!is_suspended(&app.world));
+        assert!(app.world.resource::<render::ForceRedraw>().0);
+        app.update();
+        let buffer = render::headless_buffer(&app.world).unwrap();
+        assert_eq!(*buffer, ratatui::buffer::Buffer::with_lines(vec!["after "]));
+        assert!(!app.world.resource::<render::ForceRedraw>().0);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn only_the_terminal_can_suspend() {
+        use crossterm::event::{Event, KeyCode, KeyEvent, KeyModifiers};
+
+        let ctrl_z = Event::Key(KeyEvent::new(KeyCode::Char('z'), KeyModifiers::CONTROL));
+        let mut inputs = crate::input::RawInputs::default();
+        inputs.raw.push(ctrl_z.clone());
+        assert!(!unix::ctrl_z(&inputs));
+        inputs.terminal.push(ctrl_z);
+        assert!(unix::ctrl_z(&inputs));
+    }
+}
@@ -1,28 +1,147 @@
 use bevy::{
     app::{App, First},
-    ecs::system::{Resource, ResMut, SystemId, Res},
-};
-use std::{
-    time::{Duration, Instant},
-    sync::OnceLock,
+    ecs::{
+        system::{Resource, ResMut, SystemId, Res},
+        world::World,
+    },
 };
+use std::time::{Duration, Instant};
 
-pub(crate) static MAX_SLEEP_SYSTEM: OnceLock<SystemId<(), Duration>> = OnceLock::new(); 
-pub(crate) static SHOULD_RENDER_SYSTEM: OnceLock<SystemId<(), bool>> = OnceLock::new();
+/// One-shot systems the runner and schedules call directly
+#[derive(Resource)]
+struct TimeSystems {
+    max_sleep: SystemId<(), Duration>,
+    should_render: SystemId<(), bool>,
+}
 
-pub(crate) fn build(app: &mut App) {
+pub(crate) fn build(app: &mut App, foxin: &crate::Foxin) {
     app.add_systems(First, clear_logic_timeout);
-    app.init_resource::<MaxRenderFrequency>();
+    app.insert_resource(foxin.clock.clone());
+    app.insert_resource(MaxRenderFrequency(foxin.max_fps));
     app.init_resource::<LastRenderTime>();
     app.init_resource::<RenderTimeout>();
     app.init_resource::<LogicTimeout>();
 
-    MAX_SLEEP_SYSTEM.set(app.world.register_system(max_sleep)).unwrap();
-    SHOULD_RENDER_SYSTEM.set(app.world.register_system(should_render)).unwrap();
+    let systems = TimeSystems {
+        max_sleep: app.world.register_system(max_sleep),
+        should_render: app.world.register_system(should_render),
+    };
+    app.insert_resource(systems);
+}
+
+/// How long in real time the runner may wait for input before the next
+/// frame is due
+pub(crate) fn run_max_sleep(world: &mut World) -> Duration {
+    let system = world.resource::<TimeSystems>().max_sleep;
+    let sleep = world.run_system(system).unwrap();
+    world.resource_mut::<crate::diagnostics::FrameDiagnostics>().max_sleep = sleep;
+    sleep
+}
+
+pub(crate) fn run_should_render(world: &mut World) -> bool {
+    let system = world.resource::<TimeSystems>().should_render;
+    world.run_system(system).unwrap()
 }
 
 pub(crate) fn cleanup(_: &mut App) {}
 
+/// Where foxin gets the time. Everything foxin times, and anything the game
+/// times that should replay or be tested the same way, goes through this
+/// rather than `Instant::now`.
+#[derive(Resource, Debug, Clone)]
+pub struct Clock(ClockKind);
+
+#[derive(Debug, Clone)]
+enum ClockKind {
+    Real,
+    /// Only moves when advanced
+    Manual(Instant),
+    Scaled {
+        speed: f64,
+        real_start: Instant,
+        start: Instant,
+    },
+}
+
+impl Default for Clock {
+    fn default() -> Self {
+        Self::real()
+    }
+}
+
+impl Clock {
+    pub fn real() -> Self {
+        Self(ClockKind::Real)
+    }
+
+    /// Stands still until [`advance`](Self::advance)d, for tests
+    pub fn manual() -> Self {
+        Self(ClockKind::Manual(Instant::now()))
+    }
+
+    /// Runs `speed` times as fast as real time, e.g. 4.0 to watch a replay
+    /// quickly. Speeds that aren't positive stop the clock.
+    pub fn scaled(speed: f64) -> Self {
+        let now = Instant::now();
+        Self(ClockKind::Scaled {
+            speed: speed.max(0.0),
+            real_start: now,
+            start: now,
+        })
+    }
+
+    pub fn now(&self) -> Instant {
+        match self.0 {
+            ClockKind::Real => Instant::now(),
+            ClockKind::Manual(now) => now,
+            ClockKind::Scaled { speed, real_start, start } => {
+                let elapsed = real_start.elapsed().as_secs_f64() * speed;
+                start + Duration::try_from_secs_f64(elapsed).unwrap_or(Duration::MAX)
+            },
+        }
+    }
+
+    /// Move a manual clock forward. Other clocks keep their own time.
+    pub fn advance(&mut self, by: Duration) {
+        if let ClockKind::Manual(now) = &mut self.0 {
+            *now += by;
+        }
+    }
+
+    /// Move a manual clock to `to`, which playback uses to follow the recording
+    pub(crate) fn set(&mut self, to: Instant) {
+        if let ClockKind::Manual(now) = &mut self.0 {
+            *now = to;
+        }
+    }
+
+    /// A clock that runs like this one but reads `at` right now, so time
+    /// carries on from a recording that ran out
+    pub(crate) fn continue_from(&self, at: Instant) -> Self {
+        match self.0 {
+            ClockKind::Manual(_) => Self(ClockKind::Manual(at)),
+            ClockKind::Real => Self(ClockKind::Scaled { speed: 1.0, real_start: Instant::now(), start: at }),
+            ClockKind::Scaled { speed, .. } => Self(ClockKind::Scaled { speed, real_start: Instant::now(), start: at }),
+        }
+    }
+
+    /// How long `duration` on this clock takes in real time
+    pub fn real_duration(&self, duration: Duration) -> Duration {
+        match self.0 {
+            ClockKind::Scaled { speed, .. } => {
+                Duration::try_from_secs_f64(duration.as_secs_f64() / speed).unwrap_or(Duration::MAX)
+            },
+            ClockKind::Real | ClockKind::Manual(_) => duration,
+        }
+    }
+}
+
+/// Longest the runner waits for input when nothing is due. Waking up now
+/// and then is cheap, and keeps the timeout within what `poll` can handle.
+const MAX_SLEEP: Duration = Duration::from_secs(1);
+
+/// Most frames drawn per second. Zero, or anything else that isn't a
+/// positive number, means no limit.
 #[derive(Resource)]
 pub struct MaxRenderFrequency(pub f32);
 
@@ -32,46 +151,63 @@ impl Default for MaxRenderFrequency {
     }
 }
 
+impl MaxRenderFrequency {
+    /// Shortest time between the starts of two frames
+    pub fn frame_time(&self) -> Duration {
+        if self.0 > 0.0 {
+            Duration::try_from_secs_f32(1.0 / self.0).unwrap_or(Duration::MAX)
+        } else {
+            Duration::ZERO
+        }
+    }
+}
+
 #[derive(Resource, Default)]
 struct LastRenderTime(Option<Instant>);
 
+/// When the next frame may be drawn, or `None` if nothing asked for one.
+/// The first frame is drawn whether or not anything asked.
+fn next_render_at(
+    last: Option<Instant>,
+    timeout: Option<Instant>,
+    max_freq: &MaxRenderFrequency,
+    now: Instant,
+) -> Option<Instant> {
+    let Some(last) = last else {
+        return Some(now);
+    };
+    let earliest = last.checked_add(max_freq.frame_time())?;
+    timeout.map(|timeout| timeout.max(earliest))
+}
+
+/// How long to wait from `now` until `deadline`, which may have passed
+fn sleep_until(deadline: Option<Instant>, now: Instant) -> Duration {
+    deadline
+        .map(|deadline| deadline.saturating_duration_since(now))
+        .unwrap_or(MAX_SLEEP)
+        .min(MAX_SLEEP)
+}
+
 fn should_render(
     mut time: ResMut<LastRenderTime>,
     mut render_timeout: ResMut<RenderTimeout>,
     max_freq: Res<MaxRenderFrequency>,
+    clock: Res<Clock>,
 ) -> bool {
-    fn should_rerender(time: &Option<Instant>, timeout: &Option<Instant>, max_freq: f32) -> bool {
-        let last_render_time = match time {
-            Some(t) => t,
-            None => {
-                return true;
-            }
-        };
-
-        match (max_freq, time) {
-            (0.0, _) => {},
-            (f, Some(t)) if t.elapsed().as_secs_f32() < 1.0 / f => {
-                return false;
-            },
-            _ => {},
-        }
-
-        match timeout {
-            Some(t) => *t < Instant::now(), 
-            None => false,
-        }
-    }
-
-    let should_rerender = should_rerender(&time.0, &render_timeout.0, max_freq.0);
+    let now = clock.now();
+    let should_render = next_render_at(time.0, render_timeout.0, &max_freq, now)
+        .is_some_and(|at| at <= now);
 
-    if should_rerender {
-        time.0 = Some(Instant::now());
+    if should_render {
+        time.0 = Some(now);
         render_timeout.0 = None;
     }
 
-    should_rerender
+    should_render
 }
 
+/// When the runner should next wake up to run logic. A deadline stands until
+/// it's due, however many frames run before then.
 #[derive(Resource, Default)]
 pub struct LogicTimeout(Option<Instant>);
 
@@ -79,6 +215,12 @@ impl LogicTimeout {
     pub fn by(&mut self, when: Instant) {
         self.0 = Some(self.0.map(|v| v.min(when)).unwrap_or(when));
     }
+
+    pub(crate) fn delay(&mut self, by: Duration) {
+        if let Some(at) = self.0.as_mut() {
+            *at += by;
+        }
+    }
 }
 
 #[derive(Resource, Default)]
@@ -90,32 +232,163 @@ impl RenderTimeout {
     }
 }
 
-fn clear_logic_timeout(mut logic_timeout: ResMut<LogicTimeout>) {
-    logic_timeout.0 = None;
+/// The timeout holds still while paused, so a deadline that comes due then
+/// is kept until resuming
+pub(crate) fn clear_logic_timeout(
+    mut logic_timeout: ResMut<LogicTimeout>,
+    paused: Res<crate::focus::Paused>,
+    clock: Res<Clock>,
+) {
+    if !paused.is_paused() && logic_timeout.0.is_some_and(|at| at <= clock.now()) {
+        logic_timeout.0 = None;
+    }
 }
 
+#[allow(clippy::too_many_arguments)]
 fn max_sleep(
     render_timeout: Res<RenderTimeout>,
     logic_timeout: Res<LogicTimeout>,
     last_render_time: Res<LastRenderTime>,
     max_freq: Res<MaxRenderFrequency>,
+    player: Option<Res<crate::replay::Player>>,
+    paused: Res<crate::focus::Paused>,
+    suspended: Res<crate::suspend::Suspended>,
+    timers: Res<crate::timer::Timers>,
+    clock: Res<Clock>,
 ) -> Duration {
-    let mut timeout = Duration::MAX;
-    let now = Instant::now();
+    // Everything else is on the recording's time, which only moves when
+    // the next recorded frame is played
+    if let Some(wait) = player.and_then(|player| player.real_wait(Instant::now())) {
+        return wait.min(MAX_SLEEP);
+    }
+    let now = clock.now();
+    let render_at = next_render_at(last_render_time.0, render_timeout.0, &max_freq, now)
+        .filter(|_| !suspended.is_suspended());
+    let deadlines = [
+        logic_timeout.0.filter(|_| !paused.is_paused()),
+        timers.next_deadline().filter(|_| !paused.is_paused()),
+        render_at,
+    ];
+    let sleep = sleep_until(deadlines.into_iter().flatten().min(), now);
+    clock.real_duration(sleep).min(MAX_SLEEP)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// At 8 FPS, which is exact in floating point
+    const FRAME: Duration = Duration::from_millis(125);
+
+    fn ms(ms: u64) -> Duration {
+        Duration::from_millis(ms)
+    }
 
-    if let Some(t) = logic_timeout.0 {
-        timeout = timeout.min(t - now); 
+    #[test]
+    fn first_frame_is_drawn_right_away() {
+        let now = Instant::now();
+        let freq = MaxRenderFrequency(10.0);
+        assert_eq!(next_render_at(None, None, &freq, now), Some(now));
+        assert_eq!(next_render_at(None, Some(now + ms(50)), &freq, now), Some(now));
     }
 
-    if let Some(t) = render_timeout.0 {
-        let min_render_time = match (last_render_time.0, max_freq.0) {
-            (None, _) => now,
-            (Some(_), 0.0) => now,
-            (Some(last), freq) => last + Duration::from_secs_f32(1.0 / freq),
-        };
-        
-        timeout = timeout.min(t.max(min_render_time) - now);
+    #[test]
+    fn nothing_asked_for_means_no_frame() {
+        let now = Instant::now();
+        let freq = MaxRenderFrequency(10.0);
+        assert_eq!(next_render_at(Some(now - ms(500)), None, &freq, now), None);
     }
 
-    timeout
+    #[test]
+    fn frames_wait_for_the_frequency_limit() {
+        let now = Instant::now();
+        let last = now - ms(40);
+        let freq = MaxRenderFrequency(8.0);
+        assert_eq!(freq.frame_time(), FRAME);
+        let at = next_render_at(Some(last), Some(now - ms(10)), &freq, now);
+        assert_eq!(at, Some(last + FRAME));
+        assert_eq!(sleep_until(at, now), ms(85));
+    }
+
+    #[test]
+    fn later_timeouts_are_kept() {
+        let now = Instant::now();
+        let last = now - ms(500);
+        let freq = MaxRenderFrequency(10.0);
+        let at = next_render_at(Some(last), Some(now + ms(30)), &freq, now);
+        assert_eq!(at, Some(now + ms(30)));
+    }
+
+    #[test]
+    fn unlimited_frequency_renders_as_soon_as_asked() {
+        let now = Instant::now();
+        let last = now - ms(1);
+        for freq in [0.0, -1.0, f32::NAN, f32::INFINITY] {
+            let freq = MaxRenderFrequency(freq);
+            assert_eq!(freq.frame_time(), Duration::ZERO);
+            assert_eq!(next_render_at(Some(last), Some(now), &freq, now), Some(now));
+        }
+    }
+
+    #[test]
+    fn tiny_frequency_never_overflows() {
+        let now = Instant::now();
+        let freq = MaxRenderFrequency(f32::MIN_POSITIVE);
+        assert_eq!(freq.frame_time(), Duration::MAX);
+        assert_eq!(next_render_at(Some(now), Some(now), &freq, now), None);
+    }
+
+    #[test]
+    fn past_deadlines_do_not_wait() {
+        let now = Instant::now();
+        assert_eq!(sleep_until(Some(now - ms(10)), now), Duration::ZERO);
+        assert_eq!(sleep_until(Some(now), now), Duration::ZERO);
+    }
+
+    #[test]
+    fn manual_clock_only_moves_when_advanced() {
+        let mut clock = Clock::manual();
+        let start = clock.now();
+        std::thread::sleep(ms(2));
+        assert_eq!(clock.now(), start);
+        clock.advance(ms(30));
+        assert_eq!(clock.now(), start + ms(30));
+    }
+
+    #[test]
+    fn scaled_clock_sleeps_for_less() {
+        let clock = Clock::scaled(4.0);
+        assert_eq!(clock.real_duration(ms(100)), ms(25));
+        assert!(Clock::scaled(0.0).real_duration(ms(1)) >= MAX_SLEEP);
+    }
+
+    #[test]
+    fn renders_follow_the_clock() {
+        use bevy::ecs::system::RunSystemOnce;
+
+        let mut world = World::new();
+        world.insert_resource(Clock::manual());
+        world.insert_resource(MaxRenderFrequency(8.0));
+        world.init_resource::<LastRenderTime>();
+        world.init_resource::<RenderTimeout>();
+
+        assert!(world.run_system_once(should_render));
+        assert!(!world.run_system_once(should_render));
+
+        let now = world.resource::<Clock>().now();
+        world.resource_mut::<RenderTimeout>().by(now);
+        assert!(!world.run_system_once(should_render));
+        world.resource_mut::<Clock>().advance(FRAME - ms(1));
+        assert!(!world.run_system_once(should_render));
+        world.resource_mut::<Clock>().advance(ms(1));
+        assert!(world.run_system_once(should_render));
+    }
+
+    #[test]
+    fn sleep_is_capped() {
+        let now = Instant::now();
+        assert_eq!(sleep_until(None, now), MAX_SLEEP);
+        assert_eq!(sleep_until(Some(now + ms(1) + MAX_SLEEP), now), MAX_SLEEP);
+        assert_eq!(sleep_until(Some(now + ms(5)), now), ms(5));
+    }
 }
@@ -0,0 +1,156 @@
+use bevy::{
+    app::{App, First},
+    ecs::{
+        event::Event,
+        schedule::IntoSystemConfigs,
+        system::Resource,
+        world::World,
+    },
+};
+use std::time::{Duration, Instant};
+use crate::{
+    input::GatherInput,
+    time::Clock,
+};
+
+pub(crate) fn build(app: &mut App, _: &crate::Foxin) {
+    app.init_resource::<Timers>();
+    app.add_systems(First, fire_timers.after(GatherInput));
+}
+
+pub(crate) fn cleanup(_: &mut App) {}
+
+/// Sends events at set times on the [`Clock`]. The runner wakes when the earliest is due,
+/// and the event can be read from `PreLogic` on. Nothing fires while the game
+/// is [`Paused`](crate::focus::Paused). Event types must have been added to the app.
+#[derive(Resource, Default)]
+pub struct Timers {
+    timers: Vec<Timer>,
+    next_id: u64,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub struct TimerId(u64);
+
+struct Timer {
+    id: TimerId,
+    at: Instant,
+    every: Option<Duration>,
+    send: Box<dyn Fn(&mut World) + Send + Sync>,
+}
+
+impl Timers {
+    /// Send `event` once at `at`
+    pub fn once<E: Event + Clone>(&mut self, at: Instant, event: E) -> TimerId {
+        self.add(at, None, event)
+    }
+
+    /// Send `event` at `first` and every `every` after that until cancelled.
+    /// Missed repeats are skipped rather than sent all at once.
+    pub fn repeating<E: Event + Clone>(&mut self, first: Instant, every: Duration, event: E) -> TimerId {
+        self.add(first, Some(every).filter(|every| !every.is_zero()), event)
+    }
+
+    /// Returns whether the timer was still waiting
+    pub fn cancel(&mut self, id: TimerId) -> bool {
+        let before = self.timers.len();
+        self.timers.retain(|timer| timer.id != id);
+        self.timers.len() != before
+    }
+
+    pub fn next_deadline(&self) -> Option<Instant> {
+        self.timers.iter().map(|timer| timer.at).min()
+    }
+
+    /// Push every deadline back, e.g. by the time spent paused
+    pub(crate) fn delay(&mut self, by: Duration) {
+        for timer in self.timers.iter_mut() {
+            timer.at += by;
+        }
+    }
+
+    fn add<E: Event + Clone>(&mut self, at: Instant, every: Option<Duration>, event: E) -> TimerId {
+        let id = TimerId(self.next_id);
+        self.next_id += 1;
+        self.timers.push(Timer {
+            id,
+            at,
+            every,
+            send: Box::new(move |world| { world.send_event(event.clone()); }),
+        });
+        id
+    }
+}
+
+pub(crate) fn fire_timers(world: &mut World) {
+    if crate::focus::is_paused(world) {
+        return;
+    }
+    let now = world.resource::<Clock>().now();
+    let mut due = Vec::new();
+    {
+        let mut timers = world.resource_mut::<Timers>();
+        let (ready, waiting) = std::mem::take(&mut timers.timers)
+            .into_iter()
+            .partition::<Vec<_>, _>(|timer| timer.at <= now);
+        timers.timers = waiting;
+        due.extend(ready);
+    }
+    due.sort_by_key(|timer| timer.at);
+
+    for timer in due.iter() {
+        (timer.send)(world);
+    }
+
+    let mut timers = world.resource_mut::<Timers>();
+    for mut timer in due {
+        let Some(every) = timer.every else { continue; };
+        let missed = now.saturating_duration_since(timer.at).as_nanos() / every.as_nanos();
+        let skip = every.as_nanos().saturating_mul(missed + 1);
+        timer.at += Duration::from_nanos(skip.min(u64::MAX as u128) as u64);
+        timers.timers.push(timer);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bevy::ecs::event::Events;
+
+    #[derive(Event, Clone)]
+    struct Ping;
+
+    fn pings(world: &mut World) -> usize {
+        fire_timers(world);
+        world.resource_mut::<Events<Ping>>().drain().count()
+    }
+
+    #[test]
+    fn timers_fire_on_the_clock() {
+        let mut world = World::new();
+        world.insert_resource(Clock::manual());
+        world.init_resource::<Timers>();
+        world.init_resource::<Events<Ping>>();
+        let step = Duration::from_millis(10);
+        let now = world.resource::<Clock>().now();
+
+        let mut timers = world.resource_mut::<Timers>();
+        timers.once(now + step * 3, Ping);
+        let repeating = timers.repeating(now + step, step * 2, Ping);
+        assert_eq!(timers.next_deadline(), Some(now + step));
+
+        assert_eq!(pings(&mut world), 0);
+        world.resource_mut::<Clock>().advance(step);
+        assert_eq!(pings(&mut world), 1);
+        world.resource_mut::<Clock>().advance(step * 2);
+        assert_eq!(pings(&mut world), 2);
+
+        // Missed repeats are skipped
+        world.resource_mut::<Clock>().advance(step * 7);
+        assert_eq!(pings(&mut world), 1);
+        assert_eq!(world.resource::<Timers>().next_deadline(), Some(now + step * 11));
+
+        assert!(world.resource_mut::<Timers>().cancel(repeating));
+        assert_eq!(world.resource::<Timers>().next_deadline(), None);
+    }
+}
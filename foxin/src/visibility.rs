@@ -0,0 +1,140 @@
+use bevy::{
+    app::App,
+    ecs::{
+        component::Component,
+        entity::Entity,
+        event::EventReader,
+        system::{Commands, Query},
+    },
+    hierarchy::Parent,
+};
+use crossterm::event::{KeyCode, KeyModifiers};
+use crate::{
+    input::KeyPress,
+    render::RenderAppExt,
+};
+
+pub(crate) fn build(app: &mut App, _: &crate::Foxin) {
+    app.relayout_on_change::<Visibility>();
+    app.add_systems(crate::schedule::PreLogic, toggle_on_key);
+}
+
+pub(crate) fn cleanup(_: &mut App) {}
+
+/// Hidden entities and their descendants aren't laid out or drawn, and their
+/// siblings take their space. They keep their components, so showing them
+/// again restores them as they were.
+#[derive(Component, Debug, Default, Copy, Clone, PartialEq, Eq)]
+pub enum Visibility {
+    #[default]
+    Visible,
+    Hidden,
+}
+
+impl Visibility {
+    pub fn is_visible(&self) -> bool {
+        *self == Self::Visible
+    }
+
+    pub fn toggle(&mut self) {
+        *self = match self {
+            Self::Visible => Self::Hidden,
+            Self::Hidden => Self::Visible,
+        };
+    }
+}
+
+/// Flips the entity's [`Visibility`] when this key is pressed
+#[derive(Component, Debug, Copy, Clone, PartialEq, Eq)]
+pub struct ToggleKey {
+    pub code: KeyCode,
+    pub modifiers: KeyModifiers,
+}
+
+impl ToggleKey {
+    pub fn new(code: KeyCode) -> Self {
+        Self {
+            code,
+            modifiers: KeyModifiers::NONE,
+        }
+    }
+}
+
+pub(crate) fn is_hidden(visibility: Option<&Visibility>) -> bool {
+    visibility.is_some_and(|v| !v.is_visible())
+}
+
+/// Whether the entity or any of its ancestors is hidden
+pub(crate) fn is_hidden_in_tree(
+    entity: Entity,
+    parents: &Query<&Parent>,
+    visibilities: &Query<&Visibility>,
+) -> bool {
+    std::iter::successors(Some(entity), |e| parents.get(*e).ok().map(Parent::get))
+        .any(|e| is_hidden(visibilities.get(e).ok()))
+}
+
+fn toggle_on_key(
+    mut commands: Commands,
+    mut presses: EventReader<KeyPress>,
+    mut toggles: Query<(Entity, &ToggleKey, Option<&mut Visibility>)>,
+) {
+    for press in presses.read().filter(|press| !press.repeat) {
+        for (entity, key, visibility) in toggles.iter_mut() {
+            if key.code != press.code || key.modifiers != press.modifiers {
+                continue;
+            }
+            match visibility {
+                Some(mut visibility) => visibility.toggle(),
+                None => { commands.entity(entity).insert(Visibility::Hidden); },
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bevy::{hierarchy::BuildWorldChildren, math::U16Vec2};
+    use ratatui::{
+        layout::{Constraint as Size, Direction, Layout as Split, Rect},
+        widgets::Paragraph,
+    };
+    use crate::render::{self, tests::headless_app, Constraint, DrawArea, FoxinWidget, Layer, Layout};
+
+    fn text(app: &bevy::app::App) -> String {
+        let buffer = render::headless_buffer(&app.world).unwrap();
+        buffer.content.iter().map(|cell| cell.symbol()).collect()
+    }
+
+    #[test]
+    fn hidden_subtrees_take_no_space_until_shown() {
+        let mut app = headless_app(U16Vec2::new(8, 1));
+        let (mut left, mut right) = (None, None);
+        app.world
+            .spawn((Layer(0), Layout(Split::default().direction(Direction::Horizontal))))
+            .with_children(|root| {
+                let panel = |c: &str| (
+                    Constraint(Size::Fill(1)),
+                    FoxinWidget::new(Paragraph::new(c.repeat(8))),
+                    DrawArea::default(),
+                );
+                left = Some(root.spawn(panel("L")).id());
+                right = Some(root.spawn((panel("R"), Visibility::Hidden))
+                    .with_children(|right| { right.spawn(FoxinWidget::new(Paragraph::new("X"))); })
+                    .id());
+            });
+        let (left, right) = (left.unwrap(), right.unwrap());
+        app.update();
+
+        assert_eq!(app.world.get::<DrawArea>(left).unwrap().0, Rect::new(0, 0, 8, 1));
+        assert_eq!(text(&app), "LLLLLLLL");
+
+        // Showing it asks for a frame by itself
+        *app.world.get_mut::<Visibility>(right).unwrap() = Visibility::Visible;
+        app.update();
+        assert_eq!(app.world.get::<DrawArea>(left).unwrap().0, Rect::new(0, 0, 4, 1));
+        assert_eq!(app.world.get::<DrawArea>(right).unwrap().0, Rect::new(4, 0, 4, 1));
+        assert_eq!(text(&app), "LLLLXRRR");
+    }
+}
@@ -0,0 +1,114 @@
+//! Render and lookup cost on a 1000x1000 tile world. Run with
+//! `cargo bench -p mechaknight --bench map`.
+
+use bevy::{
+    ecs::{
+        schedule::{IntoSystemConfigs, Schedule},
+        system::{Query, Res, SystemState},
+        world::World,
+    },
+    math::{IRect, IVec2, U16Vec2},
+};
+use criterion::{black_box, criterion_group, criterion_main, Criterion};
+use foxin::render::DrawBuffer;
+use mechaknight::systems::{
+    map::{self, ChunkData, ChunkSize, MapCameraCenter, Tile},
+    spatial::{self, ChunkIndex, Occupancy},
+    ui_layout::MapWindow,
+    world_entity::{self, VisibleTile, WorldPosition},
+};
+use ratatui::{buffer::Buffer, layout::Rect};
+use std::time::{Duration, Instant};
+
+const WORLD_SIZE: IVec2 = IVec2 { x: 1000, y: 1000 };
+const VIEW: Rect = Rect { x: 0, y: 0, width: 200, height: 60 };
+const OCCUPANTS: i32 = 10_000;
+
+fn world(chunk_size: ChunkSize) -> World {
+    let mut world = World::new();
+    world.insert_resource(chunk_size);
+    world.init_resource::<ChunkIndex>();
+    world.init_resource::<Occupancy>();
+    world.spawn_batch(map::generate(chunk_size, IRect::from_corners(IVec2::ZERO, WORLD_SIZE), |pos| match (pos.x ^ pos.y) % 3 {
+        0 => Tile::Wall,
+        _ => Tile::Floor,
+    }));
+    world.spawn_batch((0..OCCUPANTS).map(|i| (
+        WorldPosition(sample(i)),
+        VisibleTile::default(),
+    )));
+    world.spawn((
+        MapWindow,
+        MapCameraCenter(WORLD_SIZE / 2),
+        DrawBuffer(Buffer::empty(VIEW)),
+    ));
+    let mut index = Schedule::default();
+    index.add_systems((spatial::index_chunks, spatial::index_occupants));
+    index.run(&mut world);
+    world
+}
+
+/// Spread out positions that aren't all in the same chunks
+fn sample(i: i32) -> IVec2 {
+    IVec2 {
+        x: (i * 7919).rem_euclid(WORLD_SIZE.x),
+        y: (i * 104_729).rem_euclid(WORLD_SIZE.y),
+    }
+}
+
+fn render(c: &mut Criterion) {
+    for side in [4, 32] {
+        let mut world = world(ChunkSize(U16Vec2::splat(side)));
+        let mut schedule = Schedule::default();
+        schedule.add_systems((map::render_chunks, world_entity::render_tiles).chain());
+        c.bench_function(&format!("render 200x60 view, {side}x{side} chunks"), |b| {
+            b.iter(|| schedule.run(&mut world));
+        });
+    }
+}
+
+fn lookup(c: &mut Criterion) {
+    let mut world = world(ChunkSize::default());
+    let mut state = SystemState::<(Res<ChunkIndex>, Res<ChunkSize>, Query<&ChunkData>, Res<Occupancy>)>::new(&mut world);
+    let (index, size, chunks, occupancy) = state.get(&world);
+    let positions = (0..1000).map(sample).collect::<Vec<_>>();
+
+    c.bench_function("1000 tile lookups", |b| {
+        b.iter(|| {
+            for pos in positions.iter() {
+                black_box(index.tile_at(*pos, *size, &chunks));
+            }
+        });
+    });
+    c.bench_function("1000 occupant lookups", |b| {
+        b.iter(|| {
+            for pos in positions.iter() {
+                black_box(occupancy.at(*pos));
+            }
+        });
+    });
+}
+
+fn reindex(c: &mut Criterion) {
+    let mut world = world(ChunkSize::default());
+    let mut schedule = Schedule::default();
+    schedule.add_systems((spatial::index_chunks, spatial::index_occupants));
+    let mut query = world.query::<&mut WorldPosition>();
+    c.bench_function("reindex after 100 occupants move", |b| {
+        b.iter_custom(|iters| {
+            let mut total = Duration::ZERO;
+            for i in 0..iters as usize {
+                for mut pos in query.iter_mut(&mut world).skip(i % 100).step_by(100) {
+                    pos.0 = (pos.0 + IVec2::ONE).rem_euclid(WORLD_SIZE);
+                }
+                let start = Instant::now();
+                schedule.run(&mut world);
+                total += start.elapsed();
+            }
+            total
+        });
+    });
+}
+
+criterion_group!(benches, render, lookup, reindex);
+criterion_main!(benches);
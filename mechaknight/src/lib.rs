@@ -0,0 +1,2 @@
+pub mod systems;
+pub mod utils;
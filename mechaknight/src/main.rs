@@ -1,11 +1,18 @@
-use foxin::Foxin;
-use bevy::{MinimalPlugins, app::App};
+use foxin::{Foxin, focus::FocusPolicy, input::KeyCode, render::Screen, replay::Playback, time::Clock};
+use bevy::{MinimalPlugins, app::App, math::U16Vec2};
 use flexi_logger::{Logger, FileSpec};
+use log::error;
+use mechaknight::systems;
+use std::process::ExitCode;
 
-mod systems;
-mod utils;
-
-fn main() {
+fn main() -> ExitCode {
+    let foxin = match foxin_from_args(std::env::args().skip(1)) {
+        Ok(foxin) => foxin,
+        Err(err) => {
+            eprintln!("mechaknight: {}\n{}", err, USAGE);
+            return ExitCode::from(2);
+        },
+    };
     Logger::try_with_env()
         .unwrap()
         .log_to_file(FileSpec::default().directory("logs"))
@@ -13,7 +20,57 @@ fn main() {
         .unwrap();
     log_panics::init();
     let mut app = App::new();
-    app.add_plugins((MinimalPlugins, Foxin));
+    app.add_plugins((MinimalPlugins, foxin));
     systems::build(&mut app);
     app.run();
+
+    match foxin::error::take_exit_error() {
+        Some(err) => {
+            error!("{}", err);
+            eprintln!("mechaknight: {}", err);
+            ExitCode::FAILURE
+        },
+        None => ExitCode::SUCCESS,
+    }
+}
+
+const USAGE: &str = "\
+usage: mechaknight [--record <file>] [--replay <file> [--fast-forward <turn>]]
+                   [--speed <factor>] [--inline <lines>]";
+
+/// `--record <file>` writes a session to disk, `--replay <file>` plays one back
+/// and `--fast-forward <turn>` skips ahead through the playback.
+fn foxin_from_args(mut args: impl Iterator<Item = String>) -> Result<Foxin, String> {
+    let mut foxin = Foxin::new()
+        .pause_on_focus_loss(FocusPolicy::default())
+        // Room for the sidebar and a few map cells beside it
+        .min_terminal_size(U16Vec2::new(40, 8))
+        .diagnostics_overlay(KeyCode::F(3));
+    let mut playback = None;
+    let mut fast_forward_to = None;
+    while let Some(arg) = args.next() {
+        let flag = arg.as_str();
+        if !["--record", "--replay", "--fast-forward", "--speed", "--inline"].contains(&flag) {
+            return Err(format!("unrecognized argument {}", flag));
+        }
+        let value = args.next().ok_or_else(|| format!("{} needs a value", flag))?;
+        let invalid = || format!("invalid value for {}: {}", flag, value);
+        match flag {
+            "--record" => foxin = foxin.record(&value),
+            "--replay" => playback = Some(Playback::new(&value)),
+            "--fast-forward" => fast_forward_to = Some(value.parse().map_err(|_| invalid())?),
+            "--speed" => foxin = foxin.clock(Clock::scaled(value.parse().map_err(|_| invalid())?)),
+            _ => foxin = foxin.screen(Screen::Inline(value.parse().map_err(|_| invalid())?)),
+        }
+    }
+    match playback {
+        Some(mut playback) => {
+            playback.fast_forward_to = fast_forward_to;
+            playback.quit_key = KeyCode::Char('q');
+            foxin = foxin.playback(playback);
+        },
+        None if fast_forward_to.is_some() => return Err("--fast-forward needs --replay".into()),
+        None => {},
+    }
+    Ok(foxin)
 }
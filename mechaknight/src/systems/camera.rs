@@ -0,0 +1,125 @@
+use bevy::{
+    app::App,
+    ecs::{
+        component::Component,
+        event::EventReader,
+        schedule::IntoSystemConfigs,
+        system::Query,
+    },
+    math::{IVec2, U16Vec2},
+};
+use crate::systems::{
+    map::MapCameraCenter,
+    player,
+};
+use foxin::{
+    input::{KeyCode, KeyPress},
+    schedule::PreLogic,
+};
+
+pub fn build(app: &mut App) {
+    app.add_systems(PreLogic, (toggle_free_look, pan_free_look).chain());
+}
+
+/// How far the player can get from the center of the map window before it
+/// scrolls to follow them. Zero keeps them centered.
+#[derive(Component, Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub struct CameraDeadzone(pub U16Vec2);
+
+impl CameraDeadzone {
+    /// Kept small enough that the player stays in a view of `size`
+    pub fn within(&self, size: IVec2) -> IVec2 {
+        self.0.as_ivec2().min((size / 2 - IVec2::ONE).max(IVec2::ZERO))
+    }
+}
+
+/// Pressing `key` lets the walking keys move the map window's camera
+/// instead of the player, until it's pressed again and the camera snaps
+/// back to where it was
+#[derive(Component, Copy, Clone, Debug, PartialEq, Eq)]
+pub struct FreeLook {
+    pub key: KeyCode,
+    looking_from: Option<IVec2>,
+}
+
+impl FreeLook {
+    pub fn new(key: KeyCode) -> Self {
+        Self {
+            key,
+            looking_from: None,
+        }
+    }
+
+    pub fn is_active(&self) -> bool {
+        self.looking_from.is_some()
+    }
+}
+
+fn toggle_free_look(
+    mut presses: EventReader<KeyPress>,
+    mut cameras: Query<(&mut FreeLook, &mut MapCameraCenter)>,
+) {
+    for press in presses.read().filter(|press| !press.repeat && press.modifiers.is_empty()) {
+        for (mut look, mut camera) in cameras.iter_mut() {
+            if press.code != look.key {
+                continue;
+            }
+            match look.looking_from.take() {
+                Some(from) => camera.0 = from,
+                None => look.looking_from = Some(camera.0),
+            }
+        }
+    }
+}
+
+fn pan_free_look(
+    mut presses: EventReader<KeyPress>,
+    mut cameras: Query<(&FreeLook, &mut MapCameraCenter)>,
+) {
+    for press in presses.read().filter(|press| press.modifiers.is_empty()) {
+        let Some(delta) = player::direction(press.code) else { continue; };
+        for (_, mut camera) in cameras.iter_mut().filter(|(look, _)| look.is_active()) {
+            camera.0 += delta;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bevy::ecs::{event::Events, schedule::Schedule, world::World};
+    use foxin::input::KeyModifiers;
+
+    fn press(world: &mut World, schedule: &mut Schedule, keys: &str) {
+        for key in keys.chars() {
+            world.send_event(KeyPress {
+                code: KeyCode::Char(key),
+                modifiers: KeyModifiers::NONE,
+                repeat: false,
+            });
+        }
+        schedule.run(world);
+    }
+
+    #[test]
+    fn free_look_pans_and_snaps_back() {
+        let mut world = World::new();
+        world.init_resource::<Events<KeyPress>>();
+        let camera = world.spawn((FreeLook::new(KeyCode::Char('x')), MapCameraCenter(IVec2::new(3, 4)))).id();
+        let mut schedule = Schedule::default();
+        schedule.add_systems((toggle_free_look, pan_free_look).chain());
+        let state = |world: &World| (
+            world.get::<FreeLook>(camera).unwrap().is_active(),
+            world.get::<MapCameraCenter>(camera).unwrap().0,
+        );
+
+        // Walking keys do nothing to the camera until free look is on
+        press(&mut world, &mut schedule, "l");
+        assert_eq!(state(&world), (false, IVec2::new(3, 4)));
+        press(&mut world, &mut schedule, "x");
+        press(&mut world, &mut schedule, "llk");
+        assert_eq!(state(&world), (true, IVec2::new(5, 3)));
+        press(&mut world, &mut schedule, "x");
+        assert_eq!(state(&world), (false, IVec2::new(3, 4)));
+    }
+}
@@ -1,57 +1,120 @@
 use bevy::{
     app::{Startup, App},
     ecs::{
+        change_detection::{DetectChanges, Ref},
         component::Component,
-        entity::Entity,
-        system::{Commands, Query}, 
-        query::With,
-        schedule::{IntoSystemConfigs, SystemSet},
+        system::{Commands, Query, Res, Resource},
+        query::{Changed, Or, With},
+        schedule::{IntoSystemConfigs, IntoSystemSetConfigs, SystemSet},
     },
-    math::{URect, U16Vec2, IVec2, IRect},
+    math::{U16Vec2, IVec2, IRect},
 };
-use ratatui::layout::Position;
-use crate::{
-    systems::ui_layout::{SetupWindows, MapWindow},
-    utils::rect_ratatui_to_bevy,
+use crate::systems::{
+    spatial::{ChunkIndex, Occupancy},
+    ui_layout::MapWindow,
+    world_entity::VisibleTile,
 };
 use foxin::{
     schedule::Render,
-    render::DrawBuffer,
+    render::{DrawArea, DrawBuffer, RenderAppExt},
 };
-use log::{debug, trace};
+use log::debug;
 
 pub fn build(app: &mut App) {
-    app.add_systems(Startup, (test_chunks));
+    app.init_resource::<ChunkSize>();
+    app.add_systems(Startup, test_chunks);
     app.add_systems(Render, render_chunks.in_set(MapRender));
+    app.configure_sets(Render, MapRender.run_if(map_changed));
+    app.redraw_on_change::<ChunkData>()
+        .redraw_on_change::<ChunkPosition>()
+        .redraw_on_change::<MapCameraCenter>();
 }
 
+/// Everything drawing into the map window. It's skipped while nothing shown
+/// there changed, so the window's layer stays cached.
 #[derive(SystemSet, Hash, Copy, Clone, PartialEq, Eq, Debug)]
 pub struct MapRender;
 
-fn test_chunks(mut commands: Commands) {
-    for x in 0..10 {
-        for y in 0..10 {
-            commands.spawn((
-                ChunkData::gen_quads(),
-                ChunkPosition(IVec2 { x, y }),
-            ));
-        }
+#[allow(clippy::type_complexity)]
+fn map_changed(
+    windows: Query<(Ref<MapCameraCenter>, Ref<DrawArea>), With<MapWindow>>,
+    chunks: Query<(), Or<(Changed<ChunkData>, Changed<ChunkPosition>)>>,
+    tiles: Query<(), Changed<VisibleTile>>,
+    index: Res<ChunkIndex>,
+    occupancy: Res<Occupancy>,
+) -> bool {
+    // Layout clears the buffer whenever the window's area changes
+    windows.iter().any(|(camera, area)| camera.is_changed() || area.is_changed())
+        || !chunks.is_empty()
+        || !tiles.is_empty()
+        || index.is_changed()
+        || occupancy.is_changed()
+}
+
+/// The test level, around the player's starting point
+const LEVEL: IRect = IRect {
+    min: IVec2 { x: -20, y: -20 },
+    max: IVec2 { x: 20, y: 20 },
+};
+
+fn test_chunks(mut commands: Commands, chunk_size: Res<ChunkSize>) {
+    commands.spawn_batch(generate(*chunk_size, LEVEL, quads));
+    commands.insert_resource(LevelBounds(LEVEL));
+}
+
+/// 2x2 squares of floor and wall
+fn quads(pos: IVec2) -> Tile {
+    match (pos.x.div_euclid(2) + pos.y.div_euclid(2)).rem_euclid(2) {
+        0 => Tile::Floor,
+        _ => Tile::Wall,
     }
 }
 
-fn render_chunks(
+/// Chunks covering the tiles in `area`, with terrain from `tile`. Parts of
+/// the edge chunks outside `area` are left as void.
+pub fn generate(
+    chunk_size: ChunkSize,
+    area: IRect,
+    tile: impl Fn(IVec2) -> Tile,
+) -> impl Iterator<Item = (ChunkData, ChunkPosition)> {
+    let chunks = chunk_size.chunks_overlapping(area);
+    (chunks.min.x..chunks.max.x)
+        .flat_map(move |x| (chunks.min.y..chunks.max.y).map(move |y| ChunkPosition(IVec2 { x, y })))
+        .map(move |chunk_pos| {
+            let mut chunk = ChunkData::new(chunk_size);
+            for x in 0..chunk_size.0.x {
+                for y in 0..chunk_size.0.y {
+                    let local = U16Vec2 { x, y };
+                    let pos = chunk_size.chunk_to_world(chunk_pos.0, local);
+                    if pos.cmpge(area.min).all() && pos.cmplt(area.max).all() {
+                        chunk.set_tile(local, tile(pos));
+                    }
+                }
+            }
+            (chunk, chunk_pos)
+        })
+}
+
+pub fn render_chunks(
+    index: Res<ChunkIndex>,
+    chunk_size: Res<ChunkSize>,
     chunks: Query<(&ChunkData, &ChunkPosition)>,
     mut buffers: Query<(&mut DrawBuffer, &MapCameraCenter), With<MapWindow>>,
 ) {
+    let chunk_size = *chunk_size;
     for (mut buffer, camera_center) in buffers.iter_mut() {
         let map_bounds = camera_center.get_view_rect(&buffer);
         let view_offset = camera_center.get_view_offset(&buffer);
         debug!("Map view rect: {:#?}", map_bounds);
         debug!("Map view offset: {:#?}", view_offset);
         buffer.0.reset();
-        for (chunk, chunk_pos) in chunks.iter() {
+        let visible = chunk_size.chunks_overlapping(map_bounds);
+        let visible = (visible.min.x..visible.max.x)
+            .flat_map(|x| (visible.min.y..visible.max.y).map(move |y| IVec2 { x, y }))
+            .filter_map(|pos| chunks.get(index.get(pos)?).ok());
+        for (chunk, chunk_pos) in visible {
             let overlap = chunk_pos
-                .bounds()
+                .bounds(chunk_size)
                 .intersect(map_bounds);
             if overlap.is_empty() { continue; }
             debug!("Overlap for chunk {:?}: {:#?}", chunk_pos, overlap);
@@ -59,10 +122,11 @@ fn render_chunks(
                 for y in 0..overlap.height() {
                     let delta = IVec2 { x, y };
                     let cell_pos = overlap.min + delta;
-                    let pos_in_chunk = (cell_pos % CHUNK_SIZE.as_ivec2()).as_u16vec2();
+                    let pos_in_chunk = chunk_size.world_to_local(cell_pos);
                     let buffer_pos = (cell_pos + view_offset).as_u16vec2();
                     let cell = buffer.0.get_mut(buffer_pos.x, buffer_pos.y);
-                    match chunk.data[ChunkData::get_index(pos_in_chunk)] {
+                    match chunk.tile(pos_in_chunk) {
+                        Tile::Void => {},
                         Tile::Floor => {
                             cell.set_char('.');
                         }
@@ -76,56 +140,142 @@ fn render_chunks(
     }
 }
 
-pub const CHUNK_SIZE: U16Vec2 = U16Vec2 { x: 4, y: 4 };
+/// Tiles along each side of a chunk, chosen when the world is created.
+/// Chunks already spawned keep the size they were made with, so this must
+/// not change afterwards.
+#[derive(Resource, Copy, Clone, Debug, PartialEq, Eq)]
+pub struct ChunkSize(pub U16Vec2);
+
+impl Default for ChunkSize {
+    fn default() -> Self {
+        Self(U16Vec2 { x: 32, y: 32 })
+    }
+}
+
+/// Conversions between world positions and chunks. World positions left of
+/// or above the origin are in negative chunks, e.g. with 4x4 chunks (-1, -5)
+/// is at (3, 3) in chunk (-1, -2).
+impl ChunkSize {
+    pub fn area(&self) -> usize {
+        self.0.x as usize * self.0.y as usize
+    }
+
+    /// The chunk a world position is in
+    pub fn world_to_chunk(&self, pos: IVec2) -> IVec2 {
+        pos.div_euclid(self.0.as_ivec2())
+    }
+
+    /// Where a world position is within its chunk
+    pub fn world_to_local(&self, pos: IVec2) -> U16Vec2 {
+        pos.rem_euclid(self.0.as_ivec2()).as_u16vec2()
+    }
+
+    pub fn chunk_to_world(&self, chunk: IVec2, local: U16Vec2) -> IVec2 {
+        chunk * self.0.as_ivec2() + local.as_ivec2()
+    }
 
-#[derive(Copy, Clone, Debug, Default,)]
+    /// The world positions in a chunk
+    pub fn chunk_bounds(&self, chunk: IVec2) -> IRect {
+        let min = self.chunk_to_world(chunk, U16Vec2::ZERO);
+        IRect {
+            min,
+            max: min + self.0.as_ivec2(),
+        }
+    }
+
+    /// The chunks with any of `area` in them, as a range of chunk positions
+    /// that excludes `max` like `area` does
+    pub fn chunks_overlapping(&self, area: IRect) -> IRect {
+        if area.is_empty() {
+            return IRect::default();
+        }
+        IRect {
+            min: self.world_to_chunk(area.min),
+            max: self.world_to_chunk(area.max - IVec2::ONE) + IVec2::ONE,
+        }
+    }
+}
+
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
 pub enum Tile {
+    /// Outside the level
     #[default]
+    Void,
     Floor,
     Wall,
 }
 
+/// Everything known about a chunk's tiles, one layer per kind of data so
+/// systems only touch the layers they need
 #[derive(Component, Clone)]
 pub struct ChunkData {
-    pub data: [Tile; (CHUNK_SIZE.x * CHUNK_SIZE.y) as usize],
+    size: U16Vec2,
+    terrain: Vec<Tile>,
+    /// One bit per tile
+    explored: Vec<u64>,
+    light: Vec<u8>,
+    scent: Vec<u8>,
 }
 
-impl Default for ChunkData {
-    fn default() -> Self {
+impl ChunkData {
+    pub fn new(size: ChunkSize) -> Self {
+        let area = size.area();
         Self {
-            data: [Tile::default(); (CHUNK_SIZE.x * CHUNK_SIZE.y) as usize],
+            size: size.0,
+            terrain: vec![Tile::default(); area],
+            explored: vec![0; area.div_ceil(64)],
+            light: vec![0; area],
+            scent: vec![0; area],
         }
     }
-}
 
-impl ChunkData {
-    pub const fn get_index(pos: U16Vec2) -> usize {
-        (pos.x + pos.y * CHUNK_SIZE.x) as usize
-    }
-
-    pub fn gen_checkerboard() -> Self {
-        let mut this = Self::default();
-        for i in 0..this.data.len() {
-            this.data[i] = [
-                Tile::Floor,
-                Tile::Wall,
-            ][i % 2];
-        }
-        this
-    }
-    pub fn gen_quads() -> Self {
-        let mut this = Self::default();
-        for x in 0..CHUNK_SIZE.x {
-            for y in 0..CHUNK_SIZE.y {
-                let i = Self::get_index(U16Vec2 { x, y });
-                let v = (2 * x / CHUNK_SIZE.x) + (2 * y / CHUNK_SIZE.y);
-                this.data[i] = [
-                    Tile::Floor,
-                    Tile::Wall,
-                ][v as usize % 2];
-            }
+    pub fn size(&self) -> U16Vec2 {
+        self.size
+    }
+
+    pub fn get_index(&self, pos: U16Vec2) -> usize {
+        debug_assert!(pos.cmplt(self.size).all(), "{pos} is outside a {} chunk", self.size);
+        pos.x as usize + pos.y as usize * self.size.x as usize
+    }
+
+    pub fn tile(&self, pos: U16Vec2) -> Tile {
+        self.terrain[self.get_index(pos)]
+    }
+
+    pub fn set_tile(&mut self, pos: U16Vec2, tile: Tile) {
+        let i = self.get_index(pos);
+        self.terrain[i] = tile;
+    }
+
+    pub fn is_explored(&self, pos: U16Vec2) -> bool {
+        let i = self.get_index(pos);
+        self.explored[i / 64] & (1 << (i % 64)) != 0
+    }
+
+    pub fn set_explored(&mut self, pos: U16Vec2, explored: bool) {
+        let i = self.get_index(pos);
+        match explored {
+            true => self.explored[i / 64] |= 1 << (i % 64),
+            false => self.explored[i / 64] &= !(1 << (i % 64)),
         }
-        this
+    }
+
+    pub fn light(&self, pos: U16Vec2) -> u8 {
+        self.light[self.get_index(pos)]
+    }
+
+    pub fn set_light(&mut self, pos: U16Vec2, light: u8) {
+        let i = self.get_index(pos);
+        self.light[i] = light;
+    }
+
+    pub fn scent(&self, pos: U16Vec2) -> u8 {
+        self.scent[self.get_index(pos)]
+    }
+
+    pub fn set_scent(&mut self, pos: U16Vec2, scent: u8) {
+        let i = self.get_index(pos);
+        self.scent[i] = scent;
     }
 }
 
@@ -133,28 +283,25 @@ impl ChunkData {
 pub struct ChunkPosition(pub IVec2);
 
 impl ChunkPosition {
-    pub fn bounds(&self) -> IRect {
-        let min = IVec2 {
-            x: self.0.x * CHUNK_SIZE.x as i32,
-            y: self.0.y * CHUNK_SIZE.y as i32,
-        };
-        IRect {
-            min,
-            max: min + CHUNK_SIZE.as_ivec2(),
-        }
+    pub fn bounds(&self, size: ChunkSize) -> IRect {
+        size.chunk_bounds(self.0)
     }
 }
 
-#[derive(Component, Clone, Debug, Default)]
+/// The world positions the level covers. Cameras don't show past them.
+#[derive(Resource, Copy, Clone, Debug, PartialEq, Eq)]
+pub struct LevelBounds(pub IRect);
+
+#[derive(Component, Clone, Copy, Debug, Default, PartialEq, Eq)]
 pub struct MapCameraCenter(pub IVec2);
 
 impl MapCameraCenter {
     pub fn get_view_rect(&self, buffer: &DrawBuffer) -> IRect {
         let size = buffer.0.area.as_size();
-        IRect::from_center_size(
-            self.0,
-            IVec2 { x: size.width as i32, y: size.height as i32, }
-        )
+        let size = IVec2 { x: size.width as i32, y: size.height as i32, };
+        // Not `IRect::from_center_size`, which is a tile short on odd sizes
+        let min = self.0 - size / 2;
+        IRect { min, max: min + size }
     }
     
     /// Get a vector translating world coordinates into buffer coordinates
@@ -165,4 +312,202 @@ impl MapCameraCenter {
             y: buffer.0.area.y as i32 - view_rect.min.y,
         }
     }
+
+    /// Where a world position is drawn in the terminal, if it's in view, e.g.
+    /// to float a tooltip [`Near`](foxin::float::Anchor::Near) it
+    pub fn world_to_terminal(&self, buffer: &DrawBuffer, pos: IVec2) -> Option<U16Vec2> {
+        let view = self.get_view_rect(buffer);
+        if !(pos.cmpge(view.min).all() && pos.cmplt(view.max).all()) {
+            return None;
+        }
+        Some((pos + self.get_view_offset(buffer)).as_u16vec2())
+    }
+
+    /// Move as little as possible to bring `target` within `deadzone` of the
+    /// center in each direction
+    pub fn follow(&mut self, target: IVec2, deadzone: IVec2) {
+        self.0 = self.0.clamp(target - deadzone, target + deadzone);
+    }
+
+    /// Keep a view of `size` inside `bounds`, or centered on them along an
+    /// axis where the view is bigger
+    pub fn clamp_to(&mut self, bounds: IRect, size: IVec2) {
+        fn clamp_axis(center: i32, size: i32, min: i32, max: i32) -> i32 {
+            let (lo, hi) = (min + size / 2, max - size + size / 2);
+            match lo <= hi {
+                true => center.clamp(lo, hi),
+                false => (lo + hi).div_euclid(2),
+            }
+        }
+        self.0 = IVec2 {
+            x: clamp_axis(self.0.x, size.x, bounds.min.x, bounds.max.x),
+            y: clamp_axis(self.0.y, size.y, bounds.min.y, bounds.max.y),
+        };
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bevy::ecs::{schedule::Schedule, world::World};
+    use proptest::prelude::*;
+    use ratatui::{buffer::Buffer, layout::Rect};
+
+    fn chunk_size() -> impl Strategy<Value = ChunkSize> {
+        (1..=64u16, 1..=64u16).prop_map(|(x, y)| ChunkSize(U16Vec2 { x, y }))
+    }
+
+    fn world_pos() -> impl Strategy<Value = IVec2> {
+        (-100_000..100_000, -100_000..100_000).prop_map(|(x, y)| IVec2 { x, y })
+    }
+
+    proptest! {
+        #[test]
+        fn world_to_chunk_round_trips(size in chunk_size(), pos in world_pos()) {
+            let chunk = size.world_to_chunk(pos);
+            let local = size.world_to_local(pos);
+            prop_assert!(local.cmplt(size.0).all());
+            prop_assert_eq!(size.chunk_to_world(chunk, local), pos);
+        }
+
+        #[test]
+        fn chunks_contain_their_positions(size in chunk_size(), pos in world_pos()) {
+            let bounds = size.chunk_bounds(size.world_to_chunk(pos));
+            prop_assert!(pos.cmpge(bounds.min).all() && pos.cmplt(bounds.max).all());
+        }
+
+        #[test]
+        fn neighbours_are_adjacent_in_chunks(size in chunk_size(), pos in world_pos()) {
+            let next = pos + IVec2::X;
+            let (local, next_local) = (size.world_to_local(pos), size.world_to_local(next));
+            let (chunk, next_chunk) = (size.world_to_chunk(pos), size.world_to_chunk(next));
+            if next_chunk == chunk {
+                prop_assert_eq!(next_local.x, local.x + 1);
+            } else {
+                prop_assert_eq!(next_chunk, chunk + IVec2::X);
+                prop_assert_eq!((local.x, next_local.x), (size.0.x - 1, 0));
+            }
+        }
+
+        #[test]
+        fn overlapping_chunks_cover_the_area(
+            size in chunk_size(),
+            min in world_pos(),
+            extent in (1..200, 1..200),
+        ) {
+            let area = IRect::from_corners(min, min + IVec2::new(extent.0, extent.1));
+            let chunks = size.chunks_overlapping(area);
+            let covered = IRect {
+                min: size.chunk_bounds(chunks.min).min,
+                max: size.chunk_bounds(chunks.max - IVec2::ONE).max,
+            };
+            prop_assert_eq!(covered.union(area), covered);
+            // No chunk is entirely outside the area
+            prop_assert!((area.min - covered.min).cmplt(size.0.as_ivec2()).all());
+            prop_assert!((covered.max - area.max).cmplt(size.0.as_ivec2()).all());
+        }
+    }
+
+    #[test]
+    fn camera_stays_put_within_the_deadzone() {
+        let mut camera = MapCameraCenter(IVec2::new(-3, 5));
+        camera.follow(IVec2::new(-6, 7), IVec2::new(4, 2));
+        assert_eq!(camera.0, IVec2::new(-3, 5));
+        camera.follow(IVec2::new(-9, 8), IVec2::new(4, 2));
+        assert_eq!(camera.0, IVec2::new(-5, 6));
+    }
+
+    #[test]
+    fn camera_keeps_the_view_in_the_level() {
+        let level = IRect::from_corners(IVec2::splat(-20), IVec2::splat(20));
+        let mut camera = MapCameraCenter(IVec2::new(-18, 19));
+        camera.clamp_to(level, IVec2::new(10, 6));
+        assert_eq!(camera.0, IVec2::new(-15, 17));
+
+        // Too big to fit, so the level is centered instead
+        camera.clamp_to(level, IVec2::new(60, 42));
+        assert_eq!(camera.0, IVec2::new(0, 0));
+        let view = IRect::from_center_size(camera.0, IVec2::new(60, 42));
+        assert_eq!(view.center(), level.center());
+    }
+
+    #[test]
+    fn world_positions_in_view_map_to_the_terminal() {
+        let camera = MapCameraCenter(IVec2::new(10, 10));
+        let buffer = DrawBuffer(Buffer::empty(Rect::new(2, 1, 5, 3)));
+        assert_eq!(camera.world_to_terminal(&buffer, IVec2::new(10, 10)), Some(U16Vec2::new(4, 2)));
+        assert_eq!(camera.world_to_terminal(&buffer, IVec2::new(8, 9)), Some(U16Vec2::new(2, 1)));
+        assert_eq!(camera.world_to_terminal(&buffer, IVec2::new(12, 11)), Some(U16Vec2::new(6, 3)));
+        assert_eq!(camera.world_to_terminal(&buffer, IVec2::new(13, 11)), None);
+        assert_eq!(camera.world_to_terminal(&buffer, IVec2::new(7, 9)), None);
+    }
+
+    #[test]
+    fn clamped_views_of_odd_sizes_reach_the_level_edges() {
+        let level = IRect::from_corners(IVec2::splat(-20), IVec2::splat(20));
+        for (width, height) in [(11, 7), (9, 40), (40, 13), (1, 1)] {
+            let buffer = DrawBuffer(Buffer::empty(Rect::new(3, 2, width, height)));
+            let size = IVec2::new(width as i32, height as i32);
+            for target in [level.min, level.max] {
+                let mut camera = MapCameraCenter(target);
+                camera.clamp_to(level, size);
+                let shown = camera.get_view_rect(&buffer);
+                assert_eq!(shown.size(), size);
+                assert_eq!(shown.union(level), level, "{width}x{height} at {target}");
+                assert!(shown.min == level.min || shown.max == level.max, "{width}x{height} at {target}");
+                assert_eq!(shown.min + camera.get_view_offset(&buffer), IVec2::new(3, 2));
+            }
+        }
+    }
+
+    #[test]
+    fn explored_bits_cross_word_boundaries() {
+        // 100 tiles, so the second word is only partly used
+        let mut chunk = ChunkData::new(ChunkSize(U16Vec2::new(10, 10)));
+        let pos = |i: u16| U16Vec2::new(i % 10, i / 10);
+        for i in [0, 63, 64, 99] {
+            chunk.set_explored(pos(i), true);
+        }
+        let explored = (0..100).filter(|i| chunk.is_explored(pos(*i))).collect::<Vec<_>>();
+        assert_eq!(explored, [0, 63, 64, 99]);
+
+        chunk.set_explored(pos(63), false);
+        assert!(!chunk.is_explored(pos(63)));
+        assert!(chunk.is_explored(pos(64)));
+        chunk.set_explored(pos(64), false);
+        assert!(chunk.is_explored(pos(99)));
+        assert_eq!((0..100).filter(|i| chunk.is_explored(pos(*i))).count(), 2);
+    }
+
+    #[test]
+    fn renders_chunks_in_every_quadrant() {
+        let size = ChunkSize(U16Vec2 { x: 4, y: 4 });
+        let level = IRect::from_corners(IVec2::splat(-8), IVec2::splat(8));
+        let mut world = World::new();
+        world.insert_resource(size);
+        world.init_resource::<ChunkIndex>();
+        world.spawn_batch(generate(size, level, quads));
+        world.spawn((
+            MapWindow,
+            MapCameraCenter(IVec2::ZERO),
+            DrawBuffer(Buffer::empty(Rect::new(0, 0, 16, 16))),
+        ));
+        let mut schedule = Schedule::default();
+        schedule.add_systems((crate::systems::spatial::index_chunks, render_chunks).chain());
+        schedule.run(&mut world);
+
+        let mut buffers = world.query::<(&DrawBuffer, &MapCameraCenter)>();
+        let (buffer, camera) = buffers.single(&world);
+        let offset = camera.get_view_offset(buffer);
+        for x in level.min.x..level.max.x {
+            for y in level.min.y..level.max.y {
+                let pos = IVec2 { x, y } + offset;
+                let expected = match quads(IVec2 { x, y }) {
+                    Tile::Floor => ".",
+                    _ => "#",
+                };
+                assert_eq!(buffer.0.get(pos.x as u16, pos.y as u16).symbol(), expected, "at ({x}, {y})");
+            }
+        }
+    }
 }
@@ -15,5 +15,8 @@ add_modules!(
     ui_layout
     map
     world_entity
+    spatial
     player
+    camera
+    projectile
 );
@@ -2,8 +2,9 @@ use bevy::{
     app::{App, Startup},
     math::IVec2,
     ecs::{
+        change_detection::DetectChangesMut,
         event::EventReader,
-        system::{Commands, Query, ResMut},
+        system::{Commands, Query, Res, ResMut},
         component::Component,
         query::With,
     },
@@ -13,7 +14,9 @@ use crate::{
     systems::{
         world_entity::{WorldPosition, VisibleTile},
         ui_layout::MapWindow,
-        map::MapCameraCenter,
+        map::{ChunkData, ChunkSize, LevelBounds, MapCameraCenter, Tile},
+        camera::{CameraDeadzone, FreeLook},
+        spatial::ChunkIndex,
     },
 };
 use ratatui::{
@@ -21,11 +24,12 @@ use ratatui::{
     style::Color,
 };
 use foxin::{
+    cursor::CursorRequest,
     schedule::{Logic, MidRender},
     input::{KeyCode, KeyPress},
-    time::RenderTimeout,
+    render::DrawBuffer,
+    replay::Turn,
 };
-use std::time::Instant;
 
 pub fn build(app: &mut App) {
     app.add_systems(Startup, test_player);
@@ -36,6 +40,10 @@ pub fn build(app: &mut App) {
 #[derive(Component, Copy, Clone)]
 pub struct Player;
 
+/// The way the player last walked
+#[derive(Component, Copy, Clone, Debug, PartialEq, Eq)]
+pub struct Facing(pub IVec2);
+
 fn test_player(mut commands: Commands) {
     let mut cell = Cell::default();
     cell.set_char('@');
@@ -44,51 +52,67 @@ fn test_player(mut commands: Commands) {
         WorldPosition(IVec2::ZERO),
         VisibleTile(cell),
         Player,
+        Facing(RIGHT),
     ));
 }
 
+/// Which way a walking key goes
+pub fn direction(code: KeyCode) -> Option<IVec2> {
+    let delta = match code {
+        // Arrow keys
+        KeyCode::Left      => LEFT,
+        KeyCode::Right     => RIGHT,
+        KeyCode::Down      => DOWN,
+        KeyCode::Up        => UP,
+
+        // Num pad diagonals
+        KeyCode::PageUp    => NE,
+        KeyCode::PageDown  => SE,
+        KeyCode::End       => SW,
+        KeyCode::Home      => NW,
+
+        // Vim directions
+        KeyCode::Char('h') => LEFT,
+        KeyCode::Char('j') => DOWN,
+        KeyCode::Char('k') => UP,
+        KeyCode::Char('l') => RIGHT,
+
+        // Numpad with num lock on
+        KeyCode::Char('8') => N,
+        KeyCode::Char('9') => NE,
+        KeyCode::Char('6') => E,
+        KeyCode::Char('3') => SE,
+        KeyCode::Char('2') => S,
+        KeyCode::Char('1') => SW,
+        KeyCode::Char('4') => W,
+        KeyCode::Char('7') => NW,
+
+        _                  => return None,
+    };
+    Some(delta)
+}
+
 fn walk(
     mut presses: EventReader<KeyPress>,
-    mut render_timeout: ResMut<RenderTimeout>,
-    mut player: Query<&mut WorldPosition, With<Player>>,
+    mut player: Query<(&mut WorldPosition, &mut Facing), With<Player>>,
+    free_look: Query<&FreeLook>,
+    chunk_index: Res<ChunkIndex>,
+    chunk_size: Res<ChunkSize>,
+    chunks: Query<&ChunkData>,
+    mut turn: ResMut<Turn>,
 ) {
+    // Walking keys move the camera instead
+    if free_look.iter().any(FreeLook::is_active) {
+        presses.clear();
+        return;
+    }
+
     let mut delta = IVec2::ZERO;
     for input in presses.read() {
         if !input.modifiers.is_empty() {
             continue;
         }
-
-        delta += match input.code {
-            // Arrow keys
-            KeyCode::Left      => LEFT,
-            KeyCode::Right     => RIGHT,
-            KeyCode::Down      => DOWN,
-            KeyCode::Up        => UP,
-
-            // Num pad diagonals
-            KeyCode::PageUp    => NE,
-            KeyCode::PageDown  => SE,
-            KeyCode::End       => SW,
-            KeyCode::Home      => NW,
-
-            // Vim directions
-            KeyCode::Char('h') => LEFT,
-            KeyCode::Char('j') => DOWN,
-            KeyCode::Char('k') => UP,
-            KeyCode::Char('l') => RIGHT,
-
-            // Numpad with num lock on
-            KeyCode::Char('8') => N,
-            KeyCode::Char('9') => NE,
-            KeyCode::Char('6') => E,
-            KeyCode::Char('3') => SE,
-            KeyCode::Char('2') => S,
-            KeyCode::Char('1') => SW,
-            KeyCode::Char('4') => W,
-            KeyCode::Char('7') => NW,
-
-            _                  => IVec2::ZERO,
-        };
+        delta += direction(input.code).unwrap_or(IVec2::ZERO);
     }
 
     delta = delta.signum();
@@ -96,20 +120,54 @@ fn walk(
         return;
     }
 
-    for mut cur_pos in player.iter_mut() {
+    for (mut cur_pos, mut facing) in player.iter_mut() {
         let target_pos = cur_pos.0 + delta;
+        facing.0 = delta;
+        // There's nothing to stand on past the edge of the level
+        let tile = chunk_index.tile_at(target_pos, *chunk_size, &chunks);
+        if matches!(tile, None | Some(Tile::Void)) {
+            continue;
+        }
         cur_pos.0 = target_pos;
+        turn.0 += 1;
     }
-
-    render_timeout.by(Instant::now());
 }
 
+#[allow(clippy::type_complexity)]
 fn follow_player(
     player: Query<&WorldPosition, With<Player>>,
-    mut cameras: Query<&mut MapCameraCenter, With<MapWindow>>,
+    level: Option<Res<LevelBounds>>,
+    mut cameras: Query<(
+        &mut MapCameraCenter,
+        &DrawBuffer,
+        &mut CursorRequest,
+        Option<&CameraDeadzone>,
+        Option<&FreeLook>,
+    ), With<MapWindow>>,
 ) {
     let pos = player.get_single().unwrap();
-    for mut camera in cameras.iter_mut() {
-        camera.0 = pos.0;
+    for (mut center, buffer, mut cursor, deadzone, free_look) in cameras.iter_mut() {
+        let size = IVec2 { x: buffer.0.area.width as i32, y: buffer.0.area.height as i32 };
+        let looking = free_look.is_some_and(FreeLook::is_active);
+        // Only touch the camera when it moves, so the map isn't drawn again for nothing
+        let mut camera = *center;
+        if !looking {
+            let deadzone = deadzone.copied().unwrap_or_default();
+            camera.follow(pos.0, deadzone.within(size));
+        }
+        if let Some(level) = &level {
+            camera.clamp_to(level.0, size);
+        }
+        center.set_if_neq(camera);
+        // Keep the terminal cursor on the player, or what they're looking
+        // at, for screen readers and terminals that highlight it
+        let target = if looking { camera.0 } else { pos.0 };
+        let on_screen = target + camera.get_view_offset(buffer);
+        let area = buffer.0.area;
+        let within = (on_screen - IVec2::new(area.x as i32, area.y as i32)).max(IVec2::ZERO);
+        cursor.set_if_neq(CursorRequest {
+            position: within.as_u16vec2(),
+            ..*cursor
+        });
     }
 }
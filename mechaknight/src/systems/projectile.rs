@@ -0,0 +1,86 @@
+use bevy::{
+    app::App,
+    ecs::{
+        component::Component,
+        event::EventReader,
+        query::With,
+        system::{Commands, Query, Res, ResMut},
+    },
+};
+use crate::systems::{
+    map::{ChunkData, ChunkSize, Tile},
+    player::{Facing, Player},
+    spatial::ChunkIndex,
+    world_entity::{WorldPosition, VisibleTile},
+};
+use foxin::{
+    animate::{along_path, AnimateAppExt, Animation, Track},
+    input::{KeyCode, KeyPress},
+    replay::Turn,
+    schedule::Logic,
+    time::Clock,
+};
+use ratatui::{
+    buffer::Cell,
+    style::Color,
+};
+use std::time::Duration;
+
+/// Farthest a bolt flies, in tiles
+const RANGE: i32 = 8;
+/// How long a bolt takes to cross a tile
+const TILE_TIME: Duration = Duration::from_millis(40);
+
+pub fn build(app: &mut App) {
+    app.animate_component::<WorldPosition>();
+    app.add_systems(Logic, fire);
+}
+
+/// Flies from the player the way they last walked until it reaches a wall
+/// or its range, then disappears
+#[derive(Component, Copy, Clone)]
+pub struct Bolt;
+
+#[allow(clippy::too_many_arguments)]
+fn fire(
+    mut commands: Commands,
+    mut presses: EventReader<KeyPress>,
+    player: Query<(&WorldPosition, &Facing), With<Player>>,
+    chunk_index: Res<ChunkIndex>,
+    chunk_size: Res<ChunkSize>,
+    chunks: Query<&ChunkData>,
+    clock: Res<Clock>,
+    mut turn: ResMut<Turn>,
+) {
+    let fired = presses
+        .read()
+        .any(|press| press.code == KeyCode::Char('f') && press.modifiers.is_empty() && !press.repeat);
+    if !fired {
+        return;
+    }
+
+    let mut cell = Cell::default();
+    cell.set_char('*');
+    cell.set_fg(Color::LightCyan);
+    for (pos, facing) in player.iter() {
+        let open = |step: &i32| {
+            chunk_index.tile_at(pos.0 + facing.0 * *step, *chunk_size, &chunks) == Some(Tile::Floor)
+        };
+        let range = (1..=RANGE).take_while(open).count() as i32;
+        if range == 0 {
+            continue;
+        }
+        turn.0 += 1;
+        let from = pos.0 + facing.0;
+        let path = [from.as_vec2(), (pos.0 + facing.0 * range).as_vec2()];
+        commands.spawn((
+            WorldPosition(from),
+            VisibleTile(cell.clone()),
+            Bolt,
+            Animation::once(clock.now(), TILE_TIME, TILE_TIME * range as u32).despawn_on_finish(),
+            Track::new(move |pos: &mut WorldPosition, progress| {
+                pos.0 = along_path(&path, progress).round().as_ivec2();
+            }),
+        ));
+    }
+}
@@ -0,0 +1,195 @@
+use bevy::{
+    app::App,
+    ecs::{
+        entity::Entity,
+        query::Changed,
+        removal_detection::RemovedComponents,
+        schedule::{IntoSystemConfigs, SystemSet},
+        system::{Query, Res, ResMut, Resource},
+    },
+    math::IVec2,
+    utils::HashMap,
+};
+use crate::systems::{
+    map::{ChunkData, ChunkPosition, ChunkSize, Tile},
+    world_entity::WorldPosition,
+};
+use foxin::schedule::PostLogic;
+
+pub fn build(app: &mut App) {
+    app.init_resource::<ChunkIndex>()
+        .init_resource::<Occupancy>();
+    app.add_systems(PostLogic, (index_chunks, index_occupants).in_set(UpdateSpatialIndex));
+}
+
+/// Brings [`ChunkIndex`] and [`Occupancy`] up to date with this frame's
+/// logic. Anything run before it sees them as they were last frame.
+#[derive(SystemSet, Hash, Copy, Clone, PartialEq, Eq, Debug)]
+pub struct UpdateSpatialIndex;
+
+/// Which entity holds each chunk
+#[derive(Resource, Default, Debug)]
+pub struct ChunkIndex {
+    chunks: HashMap<IVec2, Entity>,
+    positions: HashMap<Entity, IVec2>,
+}
+
+impl ChunkIndex {
+    pub fn get(&self, chunk: IVec2) -> Option<Entity> {
+        self.chunks.get(&chunk).copied()
+    }
+
+    /// The tile at a world position, if its chunk exists
+    pub fn tile_at(&self, pos: IVec2, size: ChunkSize, chunks: &Query<&ChunkData>) -> Option<Tile> {
+        let data = chunks.get(self.get(size.world_to_chunk(pos))?).ok()?;
+        Some(data.tile(size.world_to_local(pos)))
+    }
+
+    fn insert(&mut self, entity: Entity, chunk: IVec2) {
+        self.remove(entity);
+        self.chunks.insert(chunk, entity);
+        self.positions.insert(entity, chunk);
+    }
+
+    fn remove(&mut self, entity: Entity) {
+        let Some(chunk) = self.positions.remove(&entity) else { return; };
+        // Another chunk may have been put in its place since
+        if self.chunks.get(&chunk) == Some(&entity) {
+            self.chunks.remove(&chunk);
+        }
+    }
+}
+
+/// Which entities have each [`WorldPosition`]
+#[derive(Resource, Default, Debug)]
+pub struct Occupancy {
+    cells: HashMap<IVec2, Vec<Entity>>,
+    positions: HashMap<Entity, IVec2>,
+}
+
+impl Occupancy {
+    /// Entities at a world position, in the order they arrived
+    pub fn at(&self, pos: IVec2) -> &[Entity] {
+        self.cells.get(&pos).map(Vec::as_slice).unwrap_or_default()
+    }
+
+    pub fn is_occupied(&self, pos: IVec2) -> bool {
+        self.cells.contains_key(&pos)
+    }
+
+    /// Every occupied position and who is there, in no particular order
+    pub fn iter(&self) -> impl Iterator<Item = (IVec2, &[Entity])> {
+        self.cells.iter().map(|(pos, entities)| (*pos, entities.as_slice()))
+    }
+
+    fn insert(&mut self, entity: Entity, pos: IVec2) {
+        if self.positions.get(&entity) == Some(&pos) {
+            return;
+        }
+        self.remove(entity);
+        self.cells.entry(pos).or_default().push(entity);
+        self.positions.insert(entity, pos);
+    }
+
+    fn remove(&mut self, entity: Entity) {
+        let Some(pos) = self.positions.remove(&entity) else { return; };
+        let Some(cell) = self.cells.get_mut(&pos) else { return; };
+        cell.retain(|e| *e != entity);
+        if cell.is_empty() {
+            self.cells.remove(&pos);
+        }
+    }
+}
+
+pub fn index_chunks(
+    mut index: ResMut<ChunkIndex>,
+    chunk_size: Res<ChunkSize>,
+    chunks: Query<(Entity, &ChunkPosition, &ChunkData), Changed<ChunkPosition>>,
+    mut removed: RemovedComponents<ChunkPosition>,
+) {
+    for entity in removed.read() {
+        index.remove(entity);
+    }
+    for (entity, pos, data) in chunks.iter() {
+        // Lookups go through `ChunkSize`, so every chunk has to be that size
+        assert_eq!(data.size(), chunk_size.0, "chunk at {} is the wrong size", pos.0);
+        index.insert(entity, pos.0);
+    }
+}
+
+pub fn index_occupants(
+    mut occupancy: ResMut<Occupancy>,
+    occupants: Query<(Entity, &WorldPosition), Changed<WorldPosition>>,
+    mut removed: RemovedComponents<WorldPosition>,
+) {
+    for entity in removed.read() {
+        occupancy.remove(entity);
+    }
+    for (entity, pos) in occupants.iter() {
+        occupancy.insert(entity, pos.0);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bevy::{
+        ecs::{schedule::Schedule, world::World},
+        math::U16Vec2,
+    };
+
+    fn world() -> (World, Schedule) {
+        let mut world = World::new();
+        world.insert_resource(ChunkSize(U16Vec2::new(4, 4)));
+        world.init_resource::<ChunkIndex>();
+        world.init_resource::<Occupancy>();
+        let mut schedule = Schedule::default();
+        schedule.add_systems((index_chunks, index_occupants));
+        (world, schedule)
+    }
+
+    #[test]
+    fn occupancy_follows_moves_and_despawns() {
+        let (mut world, mut schedule) = world();
+        let first = world.spawn(WorldPosition(IVec2::new(1, 2))).id();
+        let second = world.spawn(WorldPosition(IVec2::new(1, 2))).id();
+        schedule.run(&mut world);
+        assert_eq!(world.resource::<Occupancy>().at(IVec2::new(1, 2)), [first, second]);
+
+        world.get_mut::<WorldPosition>(first).unwrap().0 = IVec2::new(-3, 0);
+        schedule.run(&mut world);
+        let occupancy = world.resource::<Occupancy>();
+        assert_eq!(occupancy.at(IVec2::new(1, 2)), [second]);
+        assert_eq!(occupancy.at(IVec2::new(-3, 0)), [first]);
+
+        world.despawn(second);
+        schedule.run(&mut world);
+        let occupancy = world.resource::<Occupancy>();
+        assert!(!occupancy.is_occupied(IVec2::new(1, 2)));
+        assert_eq!(occupancy.iter().collect::<Vec<_>>(), [(IVec2::new(-3, 0), [first].as_slice())]);
+    }
+
+    #[test]
+    fn chunk_index_follows_moves_and_despawns() {
+        let (mut world, mut schedule) = world();
+        let size = ChunkSize(U16Vec2::new(4, 4));
+        let chunk = world.spawn((ChunkData::new(size), ChunkPosition(IVec2::ZERO))).id();
+        schedule.run(&mut world);
+        assert_eq!(world.resource::<ChunkIndex>().get(IVec2::ZERO), Some(chunk));
+
+        world.get_mut::<ChunkPosition>(chunk).unwrap().0 = IVec2::new(0, -1);
+        schedule.run(&mut world);
+        let index = world.resource::<ChunkIndex>();
+        assert_eq!(index.get(IVec2::ZERO), None);
+        assert_eq!(index.get(IVec2::new(0, -1)), Some(chunk));
+
+        // A chunk taking another's place keeps it when the old one goes
+        let replacement = world.spawn((ChunkData::new(size), ChunkPosition(IVec2::new(0, -1)))).id();
+        schedule.run(&mut world);
+        world.despawn(chunk);
+        schedule.run(&mut world);
+        let index = world.resource::<ChunkIndex>();
+        assert_eq!(index.get(IVec2::new(0, -1)), Some(replacement));
+        assert!(index.positions.get(&chunk).is_none());
+    }
+}
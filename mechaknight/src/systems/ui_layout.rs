@@ -5,21 +5,74 @@ use bevy::{
         schedule::{SystemSet, IntoSystemConfigs},
         component::Component,
     },
+    hierarchy::BuildChildren,
+    math::U16Vec2,
+};
+use foxin::{
+    cursor::{CursorRequest, FocusedEntity},
+    frame::Frame,
+    input::KeyCode,
+    render::{Constraint, DrawArea, DrawBuffer, FoxinWidget, Layer, Layout},
+    scroll::Scroll,
+    visibility::ToggleKey,
+};
+use ratatui::{
+    layout::{Constraint as RatatuiConstraint, Direction},
+    widgets::{Block, Paragraph},
+};
+use crate::systems::{
+    camera::{CameraDeadzone, FreeLook},
+    map::MapCameraCenter,
 };
-use foxin::render::{Layer, DrawBuffer};
-use crate::systems::map::MapCameraCenter;
 
 pub fn build(app: &mut App) {
     app.add_systems(Startup, init.in_set(SetupWindows));
 }
 
+const HELP: &str = "\
+arrows, hjkl, numpad
+  walk
+x
+  look around
+f
+  fire
+tab
+  hide this panel
+mouse wheel
+  scroll this panel
+f3
+  frame timings
+q
+  quit";
+
 fn init(mut commands: Commands) {
+    let mut focus = None;
     commands.spawn((
             Layer(0),
-            MapWindow,
-            DrawBuffer::default(),
-            MapCameraCenter::default(),
-    ));
+            Layout(ratatui::layout::Layout::default().direction(Direction::Horizontal)),
+    )).with_children(|root| {
+        let map = root.spawn((
+                MapWindow,
+                Constraint(RatatuiConstraint::Fill(1)),
+                DrawArea::default(),
+                DrawBuffer::default(),
+                Frame(Block::bordered().title(" Map ")),
+                MapCameraCenter::default(),
+                CameraDeadzone(U16Vec2::new(8, 4)),
+                FreeLook::new(KeyCode::Char('x')),
+                CursorRequest::default(),
+        )).id();
+        focus = Some(map);
+        root.spawn((
+                Constraint(RatatuiConstraint::Length(24)),
+                Frame(Block::bordered().title(" Keys ")),
+                Scroll::vertical(HELP.lines().count() as u16),
+                ToggleKey::new(KeyCode::Tab),
+        )).with_children(|panel| {
+            panel.spawn(FoxinWidget::new(Paragraph::new(HELP)));
+        });
+    });
+    commands.insert_resource(FocusedEntity(focus));
 }
 
 #[derive(SystemSet, Hash, Copy, Clone, PartialEq, Eq, Debug)]
@@ -2,14 +2,17 @@ use bevy::{
     app::{App, Startup},
     ecs::{
         component::Component,
-        system::{Query, Commands},
+        system::{Query, Commands, Res},
         schedule::IntoSystemConfigs,
     },
     math::IVec2,
 };
-use crate::systems::map::{MapRender, MapCameraCenter};
+use crate::systems::{
+    map::{render_chunks, MapRender, MapCameraCenter},
+    spatial::Occupancy,
+};
 use foxin::{
-    render::DrawBuffer,
+    render::{DrawBuffer, RenderAppExt},
     schedule::Render,
 };
 use ratatui::{
@@ -18,7 +21,9 @@ use ratatui::{
 };
 
 pub fn build(app: &mut App) {
-    app.add_systems(Render, render_tiles.after(MapRender));
+    app.add_systems(Render, render_tiles.in_set(MapRender).after(render_chunks));
+    app.redraw_on_change::<WorldPosition>()
+        .redraw_on_change::<VisibleTile>();
     app.add_systems(Startup, test_ents);
 }
 
@@ -28,17 +33,27 @@ pub struct WorldPosition(pub IVec2);
 #[derive(Component, Debug, Clone, Default)]
 pub struct VisibleTile(pub Cell);
 
-fn render_tiles(
-    tiles: Query<(&WorldPosition, &VisibleTile)>,
+pub fn render_tiles(
+    occupancy: Res<Occupancy>,
+    tiles: Query<&VisibleTile>,
     mut buffers: Query<(&mut DrawBuffer, &MapCameraCenter)>,
 ) {
     for (mut buffer, camera_center) in buffers.iter_mut() {
         let bounds = camera_center.get_view_rect(&buffer);
         let view_offset = camera_center.get_view_offset(&buffer);
-        for (pos, tile) in tiles.iter() {
-            if !bounds.contains(pos.0) { continue; }
-            let cell_pos = (pos.0 + view_offset).as_u16vec2();
-            *buffer.0.get_mut(cell_pos.x, cell_pos.y) = tile.0.clone();
+        for y in bounds.min.y..bounds.max.y {
+            for x in bounds.min.x..bounds.max.x {
+                let pos = IVec2 { x, y };
+                // The latest arrival is drawn on top
+                let tile = occupancy
+                    .at(pos)
+                    .iter()
+                    .rev()
+                    .find_map(|entity| tiles.get(*entity).ok());
+                let Some(tile) = tile else { continue; };
+                let cell_pos = (pos + view_offset).as_u16vec2();
+                *buffer.0.get_mut(cell_pos.x, cell_pos.y) = tile.0.clone();
+            }
         }
     }
 }
@@ -48,7 +63,55 @@ fn test_ents(mut commands: Commands) {
     cell.set_char('M');
     cell.set_fg(Color::Yellow);
     commands.spawn((
-            WorldPosition(IVec2::ONE * 25),
+            WorldPosition(IVec2::new(12, 8)),
             VisibleTile(cell),
     ));
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::systems::spatial::index_occupants;
+    use bevy::ecs::{schedule::Schedule, world::World};
+    use ratatui::{buffer::Buffer, layout::Rect};
+
+    fn tile(c: char) -> VisibleTile {
+        let mut cell = Cell::default();
+        cell.set_char(c);
+        VisibleTile(cell)
+    }
+
+    #[test]
+    fn only_tiles_in_view_are_drawn_latest_on_top() {
+        let mut world = World::new();
+        world.init_resource::<Occupancy>();
+        let map = world.spawn((
+            DrawBuffer(Buffer::empty(Rect::new(2, 1, 5, 3))),
+            MapCameraCenter(IVec2::new(10, 10)),
+        )).id();
+        world.spawn((WorldPosition(IVec2::new(8, 9)), tile('a')));
+        world.spawn((WorldPosition(IVec2::new(12, 11)), tile('b')));
+        world.spawn((WorldPosition(IVec2::new(12, 11)), tile('c')));
+        // Just outside the view on each side
+        world.spawn((WorldPosition(IVec2::new(7, 10)), tile('x')));
+        world.spawn((WorldPosition(IVec2::new(13, 10)), tile('x')));
+        world.spawn((WorldPosition(IVec2::new(10, 8)), tile('x')));
+        world.spawn((WorldPosition(IVec2::new(10, 12)), tile('x')));
+        // Occupants that can't be seen don't hide those that can
+        world.spawn(WorldPosition(IVec2::new(10, 10)));
+        world.spawn((WorldPosition(IVec2::new(10, 10)), tile('d')));
+        world.spawn(WorldPosition(IVec2::new(10, 10)));
+
+        let mut schedule = Schedule::default();
+        schedule.add_systems((index_occupants, render_tiles).chain());
+        schedule.run(&mut world);
+
+        let mut expected = Buffer::with_lines(vec![
+            "a    ",
+            "  d  ",
+            "    c",
+        ]);
+        expected.area = Rect::new(2, 1, 5, 3);
+        assert_eq!(world.get::<DrawBuffer>(map).unwrap().0, expected);
+    }
+}
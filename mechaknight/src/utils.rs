@@ -1,3 +1,4 @@
+#[allow(dead_code)]
 pub fn rect_ratatui_to_bevy(rect: ratatui::layout::Rect) -> bevy::math::URect {
     bevy::math::URect::new(
         rect.left() as u32,